@@ -3,7 +3,7 @@ import { useState, useEffect, useCallback } from "react";
 // WASM module types
 interface WASMModule {
   init_markov_engine: () => void;
-  process_weather_data: (jsonStr: string) => any;
+  process_weather_data: (jsonStr: string, force: boolean) => any;
   run_simulation: (days: number, initialState: string) => any;
   get_statistics: () => any;
 }
@@ -13,6 +13,7 @@ interface MatrixData {
   states: string[];
   rows: number;
   cols: number;
+  coverage?: number;
 }
 
 interface SimulationDay {
@@ -100,7 +101,7 @@ export function useMarkovChain(): UseMarkovChainReturn {
       }
 
       try {
-        const result = wasmModule.process_weather_data(jsonData);
+        const result = wasmModule.process_weather_data(jsonData, false);
         return result as MatrixData;
       } catch (err) {
         // Convert WASM errors to JavaScript Error objects
@@ -5,9 +5,18 @@ use std::sync::Mutex;
 use ndarray::Array2;
 use serde_json::Value;
 
+// Upper bound on simulated days accepted from JavaScript
+const MAX_SIMULATION_DAYS: usize = 100_000;
+
 // Global state storage for transition matrix and simulation results
 static TRANSITION_MATRIX: Mutex<Option<TransitionMatrix>> = Mutex::new(None);
 static SIMULATION_RESULTS: Mutex<Option<Vec<WeatherState>>> = Mutex::new(None);
+// Fallback state for condition strings no keyword rule matches, set from JavaScript
+static DEFAULT_STATE: Mutex<StateType> = Mutex::new(StateType::Cloudy);
+// Hash of the last input process_weather_data trained on, with the MatrixData it produced
+static LAST_PROCESSED: Mutex<Option<(u64, MatrixData)>> = Mutex::new(None);
+// Observations the stored matrix was trained on, so new days can be appended later
+static STORED_HISTORY: Mutex<Option<HistoricalData>> = Mutex::new(None);
 
 // StateType enum with Sunny, Rainy, Cloudy variants
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -28,51 +37,257 @@ impl fmt::Display for StateType {
     }
 }
 
+// Parse a state name case-insensitively
+impl std::str::FromStr for StateType {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sunny" => Ok(StateType::Sunny),
+            "rainy" => Ok(StateType::Rainy),
+            "cloudy" => Ok(StateType::Cloudy),
+            _ => Err(ParseError::InvalidData(format!(
+                "Unknown state: {}. Must be 'Sunny', 'Rainy', or 'Cloudy'", s
+            ))),
+        }
+    }
+}
+
 // WeatherState struct with state and timestamp fields
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WeatherState {
     pub state: StateType,
     pub timestamp: i64,
+    // How much this observation is trusted; only build_transition_matrix_reliability uses it
+    #[serde(default = "default_weight")]
+    pub weight: f64,
 }
 
 impl WeatherState {
     pub fn new(state: StateType, timestamp: i64) -> Self {
-        Self { state, timestamp }
+        Self { state, timestamp, weight: default_weight() }
     }
 }
 
-// TransitionMatrix struct wrapping ndarray Array2<f64>
+// Full trust for observations that don't specify a weight
+fn default_weight() -> f64 {
+    1.0
+}
+
+// Anything that can label a chain state. The counting, matrix and simulation machinery
+// only needs these bounds, so chains over words, tokens or board squares work as well
+// as weather; parsing and classification stay specific to StateType.
+pub trait ChainState: Copy + Eq + std::hash::Hash + fmt::Display {}
+
+impl<T: Copy + Eq + std::hash::Hash + fmt::Display> ChainState for T {}
+
+// TransitionMatrix struct wrapping ndarray Array2<f64>, over weather states by default
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TransitionMatrix {
+pub struct TransitionMatrix<S: ChainState = StateType> {
     pub matrix: Array2<f64>,
-    pub states: Vec<StateType>,
+    pub states: Vec<S>,
+    // Raw transition counts the probabilities were trained from, when known
+    // Imported, hand-entered and derived matrices (P^n, reversed) have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counts: Option<Array2<f64>>,
 }
 
-impl TransitionMatrix {
-    // Constructor that initializes 3x3 matrix
-    pub fn new() -> Self {
-        let matrix = Array2::<f64>::zeros((3, 3));
-        let states = vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy];
-        Self { matrix, states }
+impl<S: ChainState> TransitionMatrix<S> {
+    // Identity matrix over the given states: each state deterministically stays put
+    pub fn identity(states: Vec<S>) -> Self {
+        let matrix = Array2::<f64>::eye(states.len());
+        Self { matrix, states, counts: None }
     }
 
     // Validation method to ensure matrix is stochastic (rows sum to 1.0)
     pub fn is_stochastic(&self) -> bool {
-        const EPSILON: f64 = 1e-6;
-        
+        self.is_stochastic_tol(1e-6)
+    }
+
+    // Same check with a caller-chosen tolerance on each row sum, e.g. 1e-4 for
+    // f32-precision imports or 1e-12 for exact solver output
+    pub fn is_stochastic_tol(&self, tol: f64) -> bool {
         for row in self.matrix.rows() {
             let sum: f64 = row.sum();
-            if (sum - 1.0).abs() > EPSILON {
+            if (sum - 1.0).abs() > tol {
                 return false;
             }
         }
         true
     }
 
+    // Rescale every row to sum to exactly 1.0, repairing accumulated float drift
+    // Rows with no probability mass become uniform
+    pub fn normalize_rows(&mut self) {
+        let n = self.matrix.ncols();
+        for mut row in self.matrix.rows_mut() {
+            let sum = row.sum();
+            if sum > 0.0 {
+                row /= sum;
+            } else {
+                row.fill(1.0 / n as f64);
+            }
+        }
+    }
+
     // Get the index of a state in the states vector
-    pub fn state_index(&self, state: StateType) -> Option<usize> {
+    pub fn state_index(&self, state: S) -> Option<usize> {
         self.states.iter().position(|&s| s == state)
     }
+
+    // Index of a caller-supplied state, or an error naming it if the matrix doesn't list it
+    pub fn require_state(&self, state: S) -> Result<usize, String> {
+        self.state_index(state)
+            .ok_or_else(|| format!("State {} is not in the transition matrix", state))
+    }
+
+    // Reject shapes and entries the sampler can't handle (NaN, infinite, negative)
+    // Labels must be distinct too, since state_index only ever finds the first match
+    pub fn validate_probabilities(&self) -> Result<(), ParseError> {
+        let n = self.states.len();
+        if self.matrix.nrows() != n || self.matrix.ncols() != n {
+            return Err(ParseError::InvalidData(format!(
+                "Matrix shape {}x{} does not match {} states",
+                self.matrix.nrows(), self.matrix.ncols(), n
+            )));
+        }
+
+        for (i, state) in self.states.iter().enumerate() {
+            if self.states[..i].contains(state) {
+                return Err(ParseError::InvalidData(format!(
+                    "Duplicate state label {}; every state must appear once", state
+                )));
+            }
+        }
+
+        for ((i, j), &p) in self.matrix.indexed_iter() {
+            if !p.is_finite() || p < 0.0 {
+                return Err(ParseError::InvalidData(format!(
+                    "Invalid probability {} at cell [{}, {}] ({} -> {})",
+                    p, i, j, self.states[i], self.states[j]
+                )));
+            }
+        }
+
+        if !self.is_stochastic() {
+            return Err(ParseError::InvalidData(
+                "Matrix rows must sum to 1.0".to_string()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl TransitionMatrix {
+    // Constructor that initializes 3x3 matrix
+    pub fn new() -> Self {
+        let matrix = Array2::<f64>::zeros((3, 3));
+        let states = vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+        Self { matrix, states, counts: None }
+    }
+
+    // Validation shared by every import path (rows, bytes, JSON): valid probabilities,
+    // plus every weather state listed exactly once in any order, since statistics and
+    // the bindings look up all three states
+    pub fn validate(&self) -> Result<(), ParseError> {
+        self.validate_probabilities()?;
+        for state in [StateType::Sunny, StateType::Rainy, StateType::Cloudy] {
+            if self.state_index(state).is_none() {
+                return Err(ParseError::InvalidData(format!(
+                    "Matrix is missing state {}; it must list Sunny, Rainy and Cloudy", state
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Build a matrix from hand-entered rows and state names
+    // Rows within a small tolerance of summing to 1.0 are normalized; anything else is rejected
+    pub fn from_rows(rows: Vec<Vec<f64>>, state_names: &[String]) -> Result<Self, ParseError> {
+        const NORMALIZE_TOLERANCE: f64 = 1e-3;
+        
+        let states = state_names.iter()
+            .map(|name| name.parse::<StateType>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let n = states.len();
+        
+        if rows.len() != n || rows.iter().any(|row| row.len() != n) {
+            return Err(ParseError::InvalidData(format!(
+                "Expected a {}x{} matrix to match {} states", n, n, n
+            )));
+        }
+        
+        let mut matrix = Array2::<f64>::zeros((n, n));
+        for (i, row) in rows.iter().enumerate() {
+            for (j, &p) in row.iter().enumerate() {
+                matrix[[i, j]] = p;
+            }
+        }
+        let mut transition_matrix = TransitionMatrix { matrix, states, counts: None };
+        
+        // Check entries first so NaN or negatives aren't masked by normalization
+        for ((i, j), &p) in transition_matrix.matrix.indexed_iter() {
+            if !p.is_finite() || p < 0.0 {
+                return Err(ParseError::InvalidData(format!(
+                    "Invalid probability {} at cell [{}, {}]", p, i, j
+                )));
+            }
+        }
+        
+        for (i, row) in transition_matrix.matrix.rows().into_iter().enumerate() {
+            let sum = row.sum();
+            if (sum - 1.0).abs() > NORMALIZE_TOLERANCE {
+                return Err(ParseError::InvalidData(format!(
+                    "Row {} sums to {}, expected 1.0", i, sum
+                )));
+            }
+        }
+        transition_matrix.normalize_rows();
+        
+        transition_matrix.validate()?;
+        Ok(transition_matrix)
+    }
+
+    // Compact binary encoding (bincode) with states and dimensions included
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let wire = MatrixWire {
+            states: self.states.clone(),
+            rows: self.matrix.nrows() as u32,
+            cols: self.matrix.ncols() as u32,
+            data: self.matrix.iter().copied().collect(),
+        };
+        bincode::serialize(&wire).expect("Failed to encode matrix")
+    }
+
+    // Decode a matrix produced by to_bytes and validate it
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let wire: MatrixWire = bincode::deserialize(bytes)
+            .map_err(|e| ParseError::InvalidData(format!("Invalid matrix bytes: {}", e)))?;
+        
+        let matrix = Array2::from_shape_vec((wire.rows as usize, wire.cols as usize), wire.data)
+            .map_err(|e| ParseError::InvalidData(format!("Invalid matrix shape: {}", e)))?;
+        let transition_matrix = TransitionMatrix { matrix, states: wire.states, counts: None };
+        transition_matrix.validate()?;
+        Ok(transition_matrix)
+    }
+
+    // Deserialize a matrix from JSON and validate every entry
+    pub fn from_json(json_str: &str) -> Result<Self, ParseError> {
+        let matrix: TransitionMatrix = serde_json::from_str(json_str)
+            .map_err(|e| ParseError::JsonError(e.to_string()))?;
+        matrix.validate()?;
+        Ok(matrix)
+    }
+}
+
+// Binary wire format for TransitionMatrix: explicit shape plus row-major data
+#[derive(Serialize, Deserialize)]
+struct MatrixWire {
+    states: Vec<StateType>,
+    rows: u32,
+    cols: u32,
+    data: Vec<f64>,
 }
 
 impl Default for TransitionMatrix {
@@ -81,11 +296,61 @@ impl Default for TransitionMatrix {
     }
 }
 
+// Probabilities within this distance of each other compare equal
+const MATRIX_EQ_TOLERANCE: f64 = 1e-9;
+
+// Matrices are equal when they have the same state ordering and every probability
+// matches within MATRIX_EQ_TOLERANCE, so float noise from training doesn't split them
+impl<S: ChainState> PartialEq for TransitionMatrix<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.states == other.states
+            && self.matrix.shape() == other.matrix.shape()
+            && self.matrix.iter().zip(other.matrix.iter())
+                .all(|(a, b)| (a - b).abs() <= MATRIX_EQ_TOLERANCE)
+    }
+}
+
+// Tolerance equality isn't transitive, but matrices come from validated probabilities
+// (no NaN), so it is reflexive and good enough for HashMap keys and dedup
+impl<S: ChainState> Eq for TransitionMatrix<S> {}
+
+// Hashes the state ordering and each probability rounded to the tolerance grid.
+// Two matrices that differ by less than the tolerance but straddle a rounding boundary
+// compare equal yet hash differently, so lookups can rarely miss a near-duplicate.
+impl<S: ChainState> std::hash::Hash for TransitionMatrix<S> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.states.hash(state);
+        self.matrix.shape().hash(state);
+        for &p in self.matrix.iter() {
+            ((p / MATRIX_EQ_TOLERANCE).round() as i64).hash(state);
+        }
+    }
+}
+
+// Whether timestamps were derived from UTC instants or from local calendar dates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TimeBasis {
+    #[default]
+    Utc,
+    Local,
+}
+
+// How resample picks one state to represent each time bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleStrategy {
+    // Most common state in the bucket; ties go to the state seen first
+    Mode,
+    // The bucket's final observation
+    Last,
+}
+
 // HistoricalData struct with states vector and location string
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoricalData {
     pub states: Vec<WeatherState>,
     pub location: String,
+    #[serde(default)]
+    pub time_basis: TimeBasis,
 }
 
 impl HistoricalData {
@@ -93,6 +358,7 @@ impl HistoricalData {
         Self {
             states: Vec::new(),
             location,
+            time_basis: TimeBasis::Utc,
         }
     }
 
@@ -121,12 +387,87 @@ impl HistoricalData {
         self.states.iter()
     }
 
+    // Number of distinct weather states observed
+    pub fn distinct_states(&self) -> usize {
+        let mut seen: Vec<StateType> = Vec::new();
+        for weather_state in &self.states {
+            if !seen.contains(&weather_state.state) {
+                seen.push(weather_state.state);
+            }
+        }
+        seen.len()
+    }
+
+    // Share of expected observations actually present: the span from the earliest to the
+    // latest timestamp at one observation per `expected_cadence_secs` (both ends included),
+    // e.g. 340 of 365 days gives about 0.93. Duplicate timestamps can push it above 1.0.
+    // Fewer than two observations or a non-positive cadence give 0.0.
+    pub fn coverage(&self, expected_cadence_secs: i64) -> f64 {
+        if self.states.len() < 2 || expected_cadence_secs <= 0 {
+            return 0.0;
+        }
+        let first = self.states.iter().map(|w| w.timestamp).min().unwrap();
+        let last = self.states.iter().map(|w| w.timestamp).max().unwrap();
+        let expected = (last - first) / expected_cadence_secs + 1;
+        self.states.len() as f64 / expected as f64
+    }
+
+    // Drop observations that repeat the immediately preceding state (keeps the earliest timestamp)
+    pub fn collapse_repeats(&mut self) {
+        self.states.dedup_by(|next, previous| next.state == previous.state);
+    }
+
+    // Downsample to one observation per `bucket_secs` window (e.g. 86400 turns hourly data
+    // into a daily series). Each kept observation is stamped with its bucket's start time.
+    // Buckets with no observations are skipped rather than filled, so a gap in the input
+    // becomes a direct transition between the surrounding buckets. A non-positive
+    // `bucket_secs` returns the data unchanged.
+    pub fn resample(&self, bucket_secs: i64, strategy: ResampleStrategy) -> HistoricalData {
+        let mut resampled = HistoricalData {
+            states: Vec::new(),
+            location: self.location.clone(),
+            time_basis: self.time_basis,
+        };
+        if bucket_secs <= 0 {
+            resampled.states = self.states.clone();
+            return resampled;
+        }
+        
+        let mut sorted = self.states.clone();
+        sorted.sort_by_key(|w| w.timestamp);
+        
+        for bucket in sorted.chunk_by(|a, b| a.timestamp.div_euclid(bucket_secs) == b.timestamp.div_euclid(bucket_secs)) {
+            let state = match strategy {
+                ResampleStrategy::Last => bucket[bucket.len() - 1].state,
+                ResampleStrategy::Mode => {
+                    let count = |state: StateType| bucket.iter().filter(|w| w.state == state).count();
+                    bucket.iter().fold(bucket[0].state, |best, w| {
+                        if count(w.state) > count(best) { w.state } else { best }
+                    })
+                }
+            };
+            let start = bucket[0].timestamp.div_euclid(bucket_secs) * bucket_secs;
+            resampled.add_state(WeatherState::new(state, start));
+        }
+        
+        resampled
+    }
+
     // Get consecutive state pairs for transition counting
     pub fn state_pairs(&self) -> impl Iterator<Item = (&WeatherState, &WeatherState)> {
         self.states.iter().zip(self.states.iter().skip(1))
     }
 }
 
+// One day whose state is only known as a probability vector, e.g. "70% rainy" from an
+// external classifier. Probabilities are ordered Sunny, Rainy, Cloudy and sum to 1;
+// hard observations are one-hot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoftObservation {
+    pub timestamp: i64,
+    pub probabilities: Vec<f64>,
+}
+
 // Custom error type for parsing errors
 #[derive(Debug, Clone)]
 pub enum ParseError {
@@ -147,8 +488,57 @@ impl fmt::Display for ParseError {
 
 impl std::error::Error for ParseError {}
 
+// Error for end-to-end pipelines that can fail at any stage
+#[derive(Debug, Clone)]
+pub enum EngineError {
+    Parse(ParseError),
+    InvalidInput(String),
+    InvalidMatrix(String),
+    Random(RandomError),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Parse(e) => write!(f, "Failed to parse weather data: {}", e),
+            EngineError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            EngineError::InvalidMatrix(msg) => write!(f, "Invalid matrix: {}", msg),
+            EngineError::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<ParseError> for EngineError {
+    fn from(e: ParseError) -> Self {
+        EngineError::Parse(e)
+    }
+}
+
+impl From<RandomError> for EngineError {
+    fn from(e: RandomError) -> Self {
+        EngineError::Random(e)
+    }
+}
+
+impl From<SimulationError> for EngineError {
+    fn from(e: SimulationError) -> Self {
+        match e {
+            SimulationError::UnknownState(msg) => EngineError::InvalidInput(msg),
+            SimulationError::Random(e) => EngineError::Random(e),
+        }
+    }
+}
+
 // Weather classification function that maps API conditions to StateType
 pub fn classify_weather(conditions: &str) -> StateType {
+    classify_weather_with_default(conditions, StateType::Cloudy)
+}
+
+// Classify with a caller-chosen fallback, e.g. Sunny for a desert or Rainy for a rainforest.
+// The keyword rules are unchanged; `default` only applies to strings none of them match.
+pub fn classify_weather_with_default(conditions: &str, default: StateType) -> StateType {
     let conditions_lower = conditions.to_lowercase();
     
     // Check for rainy conditions
@@ -176,16 +566,184 @@ pub fn classify_weather(conditions: &str) -> StateType {
         return StateType::Sunny;
     }
     
-    // Default to Cloudy for unknown conditions
-    StateType::Cloudy
+    // Fall back for unknown conditions
+    default
+}
+
+// WeatherAPI condition codes mapped to states
+// Every precipitation type (rain, drizzle, sleet, snow, ice pellets, thunder) counts as Rainy
+//
+//   1000                                  Sunny / Clear              -> Sunny
+//   1003, 1006, 1009                      Partly cloudy, Cloudy, Overcast -> Cloudy
+//   1030, 1135, 1147                      Mist, Fog, Freezing fog    -> Cloudy
+//   1063, 1066, 1069, 1072, 1087          Patchy rain/snow/sleet/freezing drizzle, Thundery outbreaks -> Rainy
+//   1114, 1117                            Blowing snow, Blizzard     -> Rainy
+//   1150-1171                             Drizzle and freezing drizzle -> Rainy
+//   1180-1201                             Rain and freezing rain     -> Rainy
+//   1204-1237                             Sleet, snow, ice pellets   -> Rainy
+//   1240-1264                             Rain, sleet, snow and ice pellet showers -> Rainy
+//   1273-1282                             Rain or snow with thunder  -> Rainy
+pub fn weather_code_state(code: u32) -> Option<StateType> {
+    match code {
+        1000 => Some(StateType::Sunny),
+        1003 | 1006 | 1009 | 1030 | 1135 | 1147 => Some(StateType::Cloudy),
+        1063 | 1066 | 1069 | 1072 | 1087 | 1114 | 1117
+        | 1150 | 1153 | 1168 | 1171
+        | 1180 | 1183 | 1186 | 1189 | 1192 | 1195 | 1198 | 1201
+        | 1204 | 1207 | 1210 | 1213 | 1216 | 1219 | 1222 | 1225 | 1237
+        | 1240 | 1243 | 1246 | 1249 | 1252 | 1255 | 1258 | 1261 | 1264
+        | 1273 | 1276 | 1279 | 1282 => Some(StateType::Rainy),
+        _ => None,
+    }
+}
+
+// Classify a WeatherAPI condition code, defaulting to Cloudy for unknown codes like classify_weather
+pub fn classify_by_code(code: u32) -> StateType {
+    weather_code_state(code).unwrap_or(StateType::Cloudy)
+}
+
+// Classify a batch of condition strings in one pass
+pub fn classify_many(conditions: &[&str]) -> Vec<StateType> {
+    conditions.iter().map(|c| classify_weather(c)).collect()
+}
+
+// Optional behaviors for the weather parsers; the default matches parse_weather_data
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    // Also use the response's `current` block (today's observed condition) as a data point
+    pub include_current: bool,
+    // Sort out-of-order forecast days by date instead of rejecting them
+    pub sort_by_date: bool,
+    // Merge consecutive observations of the same state, keeping the earliest.
+    // The model then describes transitions between distinct weather episodes
+    // rather than daily persistence, so self-loop probabilities become ~0.
+    pub collapse_repeats: bool,
+    // State for condition strings no keyword rule matches; None keeps the Cloudy default
+    pub default_state: Option<StateType>,
 }
 
 // Parse weather API JSON response into HistoricalData
 pub fn parse_weather_data(json_data: &str) -> Result<HistoricalData, ParseError> {
+    parse_weather_data_with_options(json_data, &ParseOptions::default())
+}
+
+// Parse weather API JSON response into HistoricalData with explicit options
+pub fn parse_weather_data_with_options(
+    json_data: &str,
+    options: &ParseOptions,
+) -> Result<HistoricalData, ParseError> {
     // Parse the JSON string
     let data: Value = serde_json::from_str(json_data)
         .map_err(|e| ParseError::JsonError(e.to_string()))?;
     
+    parse_weather_value(&data, options)
+}
+
+// Parse a flat array of daily observations, e.g.
+//   [{"date": "2024-06-01", "condition": "Sunny"},
+//    {"date": "2024-06-02", "probs": {"sunny": 0.2, "rainy": 0.7, "cloudy": 0.1}}]
+// A `probs` object makes the day a soft observation (normalized to sum to 1); without
+// it the `condition` text is classified with classify_weather into a one-hot vector.
+// Dates must strictly increase. Feed the result to build_fuzzy_transition_matrix.
+pub fn parse_flat_observations(json_str: &str) -> Result<Vec<SoftObservation>, ParseError> {
+    let data: Value = serde_json::from_str(json_str)
+        .map_err(|e| ParseError::JsonError(e.to_string()))?;
+    let entries = data.as_array()
+        .ok_or_else(|| ParseError::InvalidData("Expected a top-level array of observations".to_string()))?;
+    
+    let states = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+    let mut observations: Vec<SoftObservation> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let date_str = entry.get("date")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ParseError::MissingField("date".to_string()))?;
+        let timestamp = parse_date_to_timestamp(date_str)
+            .map_err(|e| ParseError::InvalidData(format!("Invalid date format: {}", e)))?;
+        if observations.last().is_some_and(|previous| timestamp <= previous.timestamp) {
+            return Err(ParseError::InvalidData(format!(
+                "Observation date {} is not after the previous date", date_str
+            )));
+        }
+        
+        let probabilities = match entry.get("probs") {
+            Some(probs) => {
+                let probs: StateProbabilities = serde_json::from_value(probs.clone())
+                    .map_err(|e| ParseError::InvalidData(format!("Invalid probs on {}: {}", date_str, e)))?;
+                let values = probs.to_states(&states);
+                let sum: f64 = values.iter().sum();
+                if values.iter().any(|&p| !p.is_finite() || p < 0.0) || sum <= 0.0 {
+                    return Err(ParseError::InvalidData(format!(
+                        "Probabilities on {} must be non-negative with a positive sum", date_str
+                    )));
+                }
+                values.iter().map(|p| p / sum).collect()
+            }
+            None => {
+                let condition = entry.get("condition")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| ParseError::MissingField("condition".to_string()))?;
+                let state = classify_weather(condition);
+                states.iter().map(|&s| if s == state { 1.0 } else { 0.0 }).collect()
+            }
+        };
+        
+        observations.push(SoftObservation { timestamp, probabilities });
+    }
+    
+    if observations.len() < 2 {
+        return Err(ParseError::InvalidData(
+            "Insufficient weather data (need at least 2 days)".to_string()
+        ));
+    }
+    Ok(observations)
+}
+
+// Parse an aggregator response: a top-level array of WeatherAPI-shaped location objects
+pub fn parse_multi_location(json_str: &str) -> Result<Vec<HistoricalData>, ParseError> {
+    let data: Value = serde_json::from_str(json_str)
+        .map_err(|e| ParseError::JsonError(e.to_string()))?;
+    
+    let locations = data.as_array()
+        .ok_or_else(|| ParseError::InvalidData("Expected a top-level array of locations".to_string()))?;
+    
+    let options = ParseOptions::default();
+    locations.iter().map(|location| parse_weather_value(location, &options)).collect()
+}
+
+// Parse WeatherAPI JSON, classifying each condition text with a caller-supplied function
+// instead of the built-in keyword rules and code table. Errors from `classify` abort parsing.
+pub fn parse_weather_data_with_classifier<F>(
+    json_data: &str,
+    options: &ParseOptions,
+    mut classify: F,
+) -> Result<HistoricalData, ParseError>
+where
+    F: FnMut(&str) -> Result<StateType, String>,
+{
+    let data: Value = serde_json::from_str(json_data)
+        .map_err(|e| ParseError::JsonError(e.to_string()))?;
+    
+    parse_weather_value_with(&data, options, &mut |condition_obj, path| {
+        let condition_text = condition_obj.get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ParseError::MissingField(format!("{}.text", path)))?;
+        classify(condition_text).map_err(ParseError::InvalidData)
+    })
+}
+
+// Parse a single already-decoded WeatherAPI response
+fn parse_weather_value(data: &Value, options: &ParseOptions) -> Result<HistoricalData, ParseError> {
+    parse_weather_value_with(data, options, &mut |condition_obj, path| {
+        condition_state(condition_obj, path, options)
+    })
+}
+
+// Parse a single decoded response, mapping each `condition` object to a state with `classify`
+fn parse_weather_value_with(
+    data: &Value,
+    options: &ParseOptions,
+    classify: &mut dyn FnMut(&Value, &str) -> Result<StateType, ParseError>,
+) -> Result<HistoricalData, ParseError> {
     // Extract location information
     let location_obj = data.get("location")
         .ok_or_else(|| ParseError::MissingField("location".to_string()))?;
@@ -196,7 +754,9 @@ pub fn parse_weather_data(json_data: &str) -> Result<HistoricalData, ParseError>
         .to_string();
     
     // Create HistoricalData container
+    // Forecast dates are the location's local calendar days, not UTC instants
     let mut historical_data = HistoricalData::new(location_name);
+    historical_data.time_basis = TimeBasis::Local;
     
     // Extract forecast data
     let forecast = data.get("forecast")
@@ -207,6 +767,7 @@ pub fn parse_weather_data(json_data: &str) -> Result<HistoricalData, ParseError>
         .ok_or_else(|| ParseError::MissingField("forecast.forecastday".to_string()))?;
     
     // Process each day's weather data
+    let mut previous_timestamp: Option<i64> = None;
     for day_data in forecast_days {
         // Extract date and convert to timestamp
         let date_str = day_data.get("date")
@@ -217,6 +778,14 @@ pub fn parse_weather_data(json_data: &str) -> Result<HistoricalData, ParseError>
         let timestamp = parse_date_to_timestamp(date_str)
             .map_err(|e| ParseError::InvalidData(format!("Invalid date format: {}", e)))?;
         
+        // Transitions are only meaningful if days arrive in chronological order
+        if !options.sort_by_date && previous_timestamp.is_some_and(|previous| timestamp <= previous) {
+            return Err(ParseError::InvalidData(format!(
+                "Forecast date {} is not after the previous date", date_str
+            )));
+        }
+        previous_timestamp = Some(timestamp);
+        
         // Extract weather condition
         let day_obj = day_data.get("day")
             .ok_or_else(|| ParseError::MissingField("day".to_string()))?;
@@ -224,17 +793,24 @@ pub fn parse_weather_data(json_data: &str) -> Result<HistoricalData, ParseError>
         let condition_obj = day_obj.get("condition")
             .ok_or_else(|| ParseError::MissingField("day.condition".to_string()))?;
         
-        let condition_text = condition_obj.get("text")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| ParseError::MissingField("day.condition.text".to_string()))?;
-        
-        // Classify weather and create WeatherState
-        let state = classify_weather(condition_text);
+        let state = classify(condition_obj, "day.condition")?;
         let weather_state = WeatherState::new(state, timestamp);
         
         historical_data.add_state(weather_state);
     }
     
+    if options.sort_by_date {
+        historical_data.states.sort_by_key(|w| w.timestamp);
+    }
+    
+    if options.include_current && let Some(current) = data.get("current") {
+        merge_current_observation(&mut historical_data, current, classify)?;
+    }
+    
+    if options.collapse_repeats {
+        historical_data.collapse_repeats();
+    }
+    
     // Validate that we have enough data
     if !historical_data.is_complete() {
         return Err(ParseError::InvalidData(
@@ -245,6 +821,63 @@ pub fn parse_weather_data(json_data: &str) -> Result<HistoricalData, ParseError>
     Ok(historical_data)
 }
 
+// Helper function to classify a `condition` object
+// Prefers the numeric code, which survives localized or reworded text, and falls back to
+// the text classifier when the code is missing or not in the table
+fn condition_state(condition_obj: &Value, path: &str, options: &ParseOptions) -> Result<StateType, ParseError> {
+    if let Some(state) = condition_obj.get("code")
+        .and_then(|v| v.as_u64())
+        .and_then(|code| u32::try_from(code).ok())
+        .and_then(weather_code_state)
+    {
+        return Ok(state);
+    }
+    
+    let condition_text = condition_obj.get("text")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| ParseError::MissingField(format!("{}.text", path)))?;
+    
+    let default = options.default_state.unwrap_or(StateType::Cloudy);
+    Ok(classify_weather_with_default(condition_text, default))
+}
+
+// Helper function to merge the `current` block into parsed forecast days
+// If a forecast entry already covers the same calendar day, the observed condition
+// replaces it instead of adding a duplicate; otherwise it is inserted in timestamp order
+fn merge_current_observation(
+    historical_data: &mut HistoricalData,
+    current: &Value,
+    classify: &mut dyn FnMut(&Value, &str) -> Result<StateType, ParseError>,
+) -> Result<(), ParseError> {
+    let condition_obj = current.get("condition")
+        .ok_or_else(|| ParseError::MissingField("current.condition".to_string()))?;
+    let state = classify(condition_obj, "current.condition")?;
+    
+    let timestamp = current.get("last_updated_epoch")
+        .and_then(|v| v.as_i64())
+        .ok_or_else(|| ParseError::MissingField("current.last_updated_epoch".to_string()))?;
+    
+    // Forecast days are keyed by local date, so prefer the local `last_updated` date
+    let day_timestamp = match current.get("last_updated").and_then(|v| v.as_str()) {
+        Some(local) => parse_date_to_timestamp(local.split(' ').next().unwrap_or(local))
+            .map_err(|e| ParseError::InvalidData(format!("Invalid date format: {}", e)))?,
+        None => timestamp.div_euclid(86400) * 86400,
+    };
+    
+    if let Some(existing) = historical_data.states.iter_mut()
+        .find(|w| w.timestamp.div_euclid(86400) == day_timestamp.div_euclid(86400))
+    {
+        existing.state = state;
+    } else {
+        let position = historical_data.states.iter()
+            .position(|w| w.timestamp > timestamp)
+            .unwrap_or(historical_data.states.len());
+        historical_data.states.insert(position, WeatherState::new(state, timestamp));
+    }
+    
+    Ok(())
+}
+
 // Helper function to parse date string to Unix timestamp
 fn parse_date_to_timestamp(date_str: &str) -> Result<i64, String> {
     // Parse YYYY-MM-DD format
@@ -297,370 +930,5570 @@ fn is_leap_year(year: i32) -> bool {
     (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
 }
 
-// Build transition matrix from historical data
-pub fn build_transition_matrix(data: &HistoricalData) -> TransitionMatrix {
-    // Initialize 3x3 count matrix to track state transitions
-    let mut count_matrix = Array2::<f64>::zeros((3, 3));
-    let states = vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy];
-    
-    // Iterate through sequential state pairs and increment counts
-    for (current_state, next_state) in data.state_pairs() {
-        // Get indices for current and next states
-        let current_idx = states.iter().position(|&s| s == current_state.state).unwrap();
-        let next_idx = states.iter().position(|&s| s == next_state.state).unwrap();
-        
-        // Increment the count for this transition
-        count_matrix[[current_idx, next_idx]] += 1.0;
-    }
-    
-    // Normalize each row by dividing by row sum to get probabilities
-    let mut transition_matrix = TransitionMatrix {
-        matrix: count_matrix.clone(),
-        states: states.clone(),
-    };
+// Helper function to format a Unix timestamp as a YYYY-MM-DD date string (UTC)
+fn timestamp_to_iso_date(timestamp: i64) -> String {
+    let mut days = timestamp.div_euclid(86400);
+    let mut year: i32 = 1970;
     
-    for i in 0..3 {
-        let row_sum: f64 = count_matrix.row(i).sum();
-        
-        // If row sum is 0 (no transitions from this state), set uniform distribution
-        if row_sum > 0.0 {
-            for j in 0..3 {
-                transition_matrix.matrix[[i, j]] = count_matrix[[i, j]] / row_sum;
-            }
+    // Walk whole years forwards or backwards from the epoch
+    loop {
+        let year_length = if is_leap_year(year) { 366 } else { 365 };
+        if days >= year_length {
+            days -= year_length;
+            year += 1;
+        } else if days < 0 {
+            year -= 1;
+            days += if is_leap_year(year) { 366 } else { 365 };
         } else {
-            // Set uniform distribution (1/3 for each state)
-            for j in 0..3 {
-                transition_matrix.matrix[[i, j]] = 1.0 / 3.0;
-            }
+            break;
         }
     }
     
-    // Validate that all rows sum to 1.0 within floating-point tolerance
-    debug_assert!(
-        transition_matrix.is_stochastic(),
-        "Transition matrix is not stochastic"
-    );
+    // Walk months within the year
+    let days_in_month = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    let mut month = 1;
+    for (m, &length) in days_in_month.iter().enumerate() {
+        let length = if m == 1 && is_leap_year(year) { length + 1 } else { length };
+        if days < length {
+            break;
+        }
+        days -= length;
+        month += 1;
+    }
     
-    transition_matrix
+    format!("{:04}-{:02}-{:02}", year, month, days + 1)
 }
 
-// Simulate weather using probabilistic sampling
-pub fn simulate_weather(
-    matrix: &TransitionMatrix,
-    initial_state: StateType,
-    days: usize,
-) -> Vec<WeatherState> {
-    
-    // Initialize result vector with initial state
-    let mut results = Vec::with_capacity(days);
-    let initial_timestamp = 0; // Starting timestamp
-    results.push(WeatherState::new(initial_state, initial_timestamp));
-    
-    let mut current_state = initial_state;
-    
-    // For each day, simulate the next state
-    for day in 1..days {
-        // Get current state's transition probabilities
-        let current_idx = matrix.state_index(current_state).unwrap();
-        let probabilities = matrix.matrix.row(current_idx);
-        
-        // Use weighted random sampling to select next state based on probabilities
-        let next_state = weighted_random_sample(&matrix.states, probabilities.as_slice().unwrap());
-        
-        // Append selected state to results with timestamp
-        let timestamp = initial_timestamp + (day as i64 * 86400); // Add days in seconds
-        results.push(WeatherState::new(next_state, timestamp));
-        
-        current_state = next_state;
+// Data-quality issues detected while training a matrix
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DataWarning {
+    // Fewer than two distinct states: the chain can't model any real transitions
+    TooFewDistinctStates(usize),
+    // Rows drifted off summing to 1 and were re-normalized by the builder
+    RepairedRows,
+}
+
+impl fmt::Display for DataWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataWarning::TooFewDistinctStates(count) => write!(
+                f,
+                "Only {} distinct weather state(s) observed; the model cannot learn real transitions",
+                count
+            ),
+            DataWarning::RepairedRows => write!(
+                f,
+                "Transition rows did not sum to 1 and were re-normalized"
+            ),
+        }
     }
-    
-    results
 }
 
-// Helper function for weighted random sampling
-fn weighted_random_sample(states: &[StateType], probabilities: &[f64]) -> StateType {
-    // Generate a random number between 0 and 1
-    let mut buf = [0u8; 8];
-    getrandom::getrandom(&mut buf).expect("Failed to generate random number");
-    let random_value = u64::from_le_bytes(buf) as f64 / u64::MAX as f64;
+// Build a transition matrix and report data-quality warnings alongside it
+pub fn build_transition_matrix_checked(data: &HistoricalData) -> (TransitionMatrix, Vec<DataWarning>) {
+    let mut warnings = Vec::new();
+    let distinct = data.distinct_states();
+    if distinct < 2 {
+        warnings.push(DataWarning::TooFewDistinctStates(distinct));
+    }
     
-    // Use cumulative probabilities to select a state
-    let mut cumulative = 0.0;
-    for (i, &prob) in probabilities.iter().enumerate() {
-        cumulative += prob;
-        if random_value <= cumulative {
-            return states[i];
-        }
+    let (matrix, repaired) = build_and_repair(data);
+    if repaired {
+        warnings.push(DataWarning::RepairedRows);
     }
+    (matrix, warnings)
+}
+
+// Build transition matrix from historical data; the weather case of build_chain_matrix,
+// sharing its counting (chain_counts) and row repair
+pub fn build_transition_matrix(data: &HistoricalData) -> TransitionMatrix {
+    build_and_repair(data).0
+}
+
+// Helper function to build a matrix from counts and report whether it needed repair
+fn build_and_repair(data: &HistoricalData) -> (TransitionMatrix, bool) {
+    // Count state transitions, then normalize each row to probabilities
+    let count_matrix = transition_counts(data);
+    let mut transition_matrix = counts_to_matrix(&count_matrix);
     
-    // Fallback to last state (should not happen with valid probabilities)
-    states[states.len() - 1]
+    let repaired = repair_rows(&mut transition_matrix);
+    (transition_matrix, repaired)
 }
 
-// Calculate steady-state distribution using power iteration method
-pub fn calculate_steady_state(matrix: &TransitionMatrix) -> Vec<f64> {
-    const MAX_ITERATIONS: usize = 1000;
-    const CONVERGENCE_THRESHOLD: f64 = 1e-8;
-    
-    let n = matrix.matrix.nrows();
-    let mut current_matrix = matrix.matrix.clone();
-    let mut previous_matrix = matrix.matrix.clone();
-    
-    // Use power iteration method: multiply matrix by itself repeatedly
-    for iteration in 0..MAX_ITERATIONS {
-        // Multiply matrix by itself
-        current_matrix = current_matrix.dot(&matrix.matrix);
-        
-        // Check for convergence when successive iterations differ by less than threshold
-        if iteration > 0 {
-            let mut max_diff = 0.0;
-            for i in 0..n {
-                for j in 0..n {
-                    let diff = (current_matrix[[i, j]] - previous_matrix[[i, j]]).abs();
-                    if diff > max_diff {
-                        max_diff = diff;
-                    }
-                }
-            }
-            
-            // If converged, extract stationary distribution
-            if max_diff < CONVERGENCE_THRESHOLD {
-                // Extract the first row (all rows should be identical at steady state)
-                return current_matrix.row(0).to_vec();
-            }
-        }
-        
-        previous_matrix = current_matrix.clone();
+// Helper function enforcing that all rows sum to 1.0 within floating-point tolerance
+// This runs in release builds too, so a drifted matrix is never handed to the sampler
+fn repair_rows<S: ChainState>(matrix: &mut TransitionMatrix<S>) -> bool {
+    if matrix.is_stochastic() {
+        return false;
     }
-    
-    // If we didn't converge, return the current approximation
-    // Extract stationary distribution from converged matrix (first row)
-    current_matrix.row(0).to_vec()
+    matrix.normalize_rows();
+    true
 }
 
-// WASM Bindings and JavaScript Interface
-
-#[wasm_bindgen]
-pub fn init_markov_engine() -> Result<(), JsValue> {
-    // Set up panic hook for better error messages in browser console
-    #[cfg(feature = "console_error_panic_hook")]
-    console_error_panic_hook::set_once();
-    
-    // Initialize random number generator for WASM environment
-    // getrandom is already configured with "js" feature in Cargo.toml
-    
-    // Clear any existing state
-    *TRANSITION_MATRIX.lock().unwrap() = None;
-    *SIMULATION_RESULTS.lock().unwrap() = None;
+// Build a transition matrix where each transition counts with the weight of the day it
+// leaves from, so low-confidence sources pull the estimate proportionally less. With
+// every weight at 1.0 this matches build_transition_matrix exactly; negative weights
+// count as 0. There is no separate time-decay builder: to combine reliability with a
+// recency decay, multiply the decay factor into each day's weight before calling this,
+// since the two weightings compose multiplicatively.
+pub fn build_transition_matrix_reliability(data: &HistoricalData) -> TransitionMatrix {
+    let count_matrix = weather_counts_weighted(
+        data.state_pairs().map(|(current, next)| (current.state, next.state, current.weight.max(0.0)))
+    );
     
-    Ok(())
+    let mut transition_matrix = counts_to_matrix(&count_matrix);
+    repair_rows(&mut transition_matrix);
+    transition_matrix
 }
 
-#[wasm_bindgen]
-pub fn process_weather_data(json_str: &str) -> Result<JsValue, JsValue> {
-    // Call parse_weather_data to convert JSON to HistoricalData
-    let historical_data = parse_weather_data(json_str)
-        .map_err(|e| JsValue::from_str(&format!("Failed to parse weather data: {}", e)))?;
-    
-    // Call build_transition_matrix to generate transition matrix
-    let matrix = build_transition_matrix(&historical_data);
+// Build a transition matrix from only the transitions whose source day has a timestamp
+// in [start_ts, end_ts). Membership is decided by the source day alone, so a transition
+// from the last day in range into the first day after it still counts: it describes
+// what followed weather inside the window.
+pub fn build_transition_matrix_range(data: &HistoricalData, start_ts: i64, end_ts: i64) -> TransitionMatrix {
+    let count_matrix = weather_counts_weighted(
+        data.state_pairs()
+            .filter(|(current, _)| (start_ts..end_ts).contains(&current.timestamp))
+            .map(|(current, next)| (current.state, next.state, 1.0))
+    );
     
-    // Validate the matrix is stochastic
-    if !matrix.is_stochastic() {
-        return Err(JsValue::from_str("Generated transition matrix is not stochastic"));
+    let mut transition_matrix = counts_to_matrix(&count_matrix);
+    repair_rows(&mut transition_matrix);
+    transition_matrix
+}
+
+// Empirical-Bayes smoothing: each row blends its observed distribution with the
+// overall frequency of each state across all days, weighting the marginal by
+// strength / (strength + row_count). Well-observed rows stay near their data while
+// sparse rows lean toward the local climate instead of uniform. strength = 0 (or
+// negative) gives the plain estimate, and a row with no data at all takes the marginal.
+pub fn build_transition_matrix_shrinkage(data: &HistoricalData, strength: f64) -> TransitionMatrix {
+    let count_matrix = transition_counts(data);
+    let mut transition_matrix = counts_to_matrix(&count_matrix);
+    let strength = strength.max(0.0);
+    if strength == 0.0 || data.is_empty() {
+        return transition_matrix;
     }
     
-    // Store matrix in static storage for later access
-    *TRANSITION_MATRIX.lock().unwrap() = Some(matrix.clone());
-    
-    // Serialize matrix to JsValue using serde-wasm-bindgen
-    let matrix_data = MatrixData {
-        matrix: matrix.matrix.as_slice().unwrap().to_vec(),
-        states: matrix.states.iter().map(|s| s.to_string()).collect(),
-        rows: matrix.matrix.nrows(),
-        cols: matrix.matrix.ncols(),
-    };
+    let states = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+    let marginal: Vec<f64> = states.iter()
+        .map(|&state| data.iter().filter(|s| s.state == state).count() as f64 / data.len() as f64)
+        .collect();
     
-    serde_wasm_bindgen::to_value(&matrix_data)
-        .map_err(|e| JsValue::from_str(&format!("Failed to serialize matrix: {}", e)))
+    for (mut row, count_row) in transition_matrix.matrix.rows_mut().into_iter().zip(count_matrix.rows()) {
+        let row_count = count_row.sum();
+        let shrink = strength / (strength + row_count);
+        for (p, &prior) in row.iter_mut().zip(marginal.iter()) {
+            // An empty row holds uniform here, but shrink is 1 so it is fully replaced
+            *p = (1.0 - shrink) * *p + shrink * prior;
+        }
+    }
+    repair_rows(&mut transition_matrix);
+    transition_matrix
 }
 
-#[wasm_bindgen]
-pub fn run_simulation(days: usize, initial_state_str: &str) -> Result<JsValue, JsValue> {
-    // Parse initial state string to StateType enum
-    let initial_state = match initial_state_str.to_lowercase().as_str() {
-        "sunny" => StateType::Sunny,
-        "rainy" => StateType::Rainy,
-        "cloudy" => StateType::Cloudy,
-        _ => return Err(JsValue::from_str(&format!("Invalid initial state: {}. Must be 'Sunny', 'Rainy', or 'Cloudy'", initial_state_str))),
-    };
-    
-    // Retrieve stored transition matrix from static storage
-    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
-    let matrix = matrix_guard.as_ref()
-        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
-    
-    // Call simulate_weather with matrix, initial state, and days
-    let simulation_results = simulate_weather(matrix, initial_state, days);
-    
-    // Store simulation results for statistics calculation
-    *SIMULATION_RESULTS.lock().unwrap() = Some(simulation_results.clone());
+// Train one matrix per dataset, keyed by location name
+pub fn train_all(datasets: &[HistoricalData]) -> Vec<(String, TransitionMatrix)> {
+    datasets.iter()
+        .map(|data| (data.location.clone(), build_transition_matrix(data)))
+        .collect()
+}
+
+// Draw one plausible matrix from the Bayesian posterior: each row is sampled from
+// Dir(counts + prior_alpha) by drawing independent Gamma(shape, 1) variables and
+// normalizing them. Gamma draws use Marsaglia-Tsang squeeze sampling (with the
+// U^(1/shape) boost for shapes below 1) on a SeededRandom stream, so the same seed
+// always yields the same matrix. Negative priors are treated as 0, and a row with no
+// counts and no prior mass falls back to uniform like build_transition_matrix.
+pub fn posterior_sample(data: &HistoricalData, prior_alpha: f64, seed: u64) -> TransitionMatrix {
+    let counts = transition_counts(data);
+    let mut rng = SeededRandom::new(seed);
+    let mut matrix = TransitionMatrix::new();
     
-    // Serialize simulation results to JsValue
-    let results_data: Vec<SimulationDay> = simulation_results.iter().enumerate().map(|(idx, ws)| {
-        SimulationDay {
-            day: idx,
-            state: ws.state.to_string(),
-            timestamp: ws.timestamp,
+    for (mut row, count_row) in matrix.matrix.rows_mut().into_iter().zip(counts.rows()) {
+        for (p, &count) in row.iter_mut().zip(count_row.iter()) {
+            *p = sample_gamma(count + prior_alpha.max(0.0), &mut rng);
         }
-    }).collect();
-    
-    serde_wasm_bindgen::to_value(&results_data)
-        .map_err(|e| JsValue::from_str(&format!("Failed to serialize simulation results: {}", e)))
+    }
+    matrix.normalize_rows();
+    matrix.counts = Some(counts);
+    matrix
 }
 
-#[wasm_bindgen]
-pub fn get_statistics() -> Result<JsValue, JsValue> {
-    // Retrieve stored transition matrix
-    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
-    let matrix = matrix_guard.as_ref()
-        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
-    
-    // Calculate steady-state distribution using calculate_steady_state
-    let steady_state = calculate_steady_state(matrix);
-    
-    // Compute state distribution from last simulation results
-    let simulation_guard = SIMULATION_RESULTS.lock().unwrap();
-    let state_distribution = if let Some(results) = simulation_guard.as_ref() {
-        calculate_state_distribution(results)
-    } else {
-        // If no simulation has been run, return empty distribution
-        vec![0.0, 0.0, 0.0]
-    };
-    
-    // Calculate average streak lengths for each state
-    let average_streaks = if let Some(results) = simulation_guard.as_ref() {
-        calculate_average_streaks(results)
-    } else {
-        vec![0.0, 0.0, 0.0]
-    };
+// Standard normal draw via the Box-Muller transform
+fn sample_standard_normal(rng: &mut impl RandomSource) -> f64 {
+    // 1 - u keeps the logarithm's argument in (0, 1]
+    let u1 = 1.0 - rng.next_f64();
+    let u2 = rng.next_f64();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+// Gamma(shape, 1) draw using Marsaglia and Tsang's method; 0 for non-positive shapes
+fn sample_gamma(shape: f64, rng: &mut impl RandomSource) -> f64 {
+    if shape <= 0.0 {
+        return 0.0;
+    }
+    if shape < 1.0 {
+        // Gamma(a) = Gamma(a + 1) * U^(1/a)
+        let u = 1.0 - rng.next_f64();
+        return sample_gamma(shape + 1.0, rng) * u.powf(1.0 / shape);
+    }
     
-    // Serialize all statistics to JsValue as structured object
-    let statistics = Statistics {
-        steady_state: StateProbabilities {
-            sunny: steady_state[0],
-            rainy: steady_state[1],
-            cloudy: steady_state[2],
-        },
-        distribution: StateProbabilities {
-            sunny: state_distribution[0],
-            rainy: state_distribution[1],
-            cloudy: state_distribution[2],
-        },
-        average_streaks: StateProbabilities {
-            sunny: average_streaks[0],
-            rainy: average_streaks[1],
-            cloudy: average_streaks[2],
-        },
-    };
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = sample_standard_normal(rng);
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u = 1.0 - rng.next_f64();
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+// Raise every probability below `floor` to `floor` and renormalize each row, so no
+// transition is ever impossible or certain. Works on any matrix, including imported ones.
+// This is a post-hoc alternative to Laplace (add-one) smoothing of the counts, which
+// already keeps every entry above zero; applying both smooths the model twice.
+// Renormalizing can leave clipped entries slightly under `floor`, and a floor of
+// 1/n or more makes every row uniform.
+pub fn clip_probabilities(matrix: &mut TransitionMatrix, floor: f64) {
+    matrix.matrix.mapv_inplace(|p| if p < floor { floor } else { p });
+    matrix.normalize_rows();
+}
+
+// Count consecutive transitions in any state sequence; rows and columns follow `states`
+// Errors on a sequence entry that isn't one of `states`
+pub fn chain_counts<S: ChainState>(states: &[S], sequence: &[S]) -> Result<Array2<f64>, String> {
+    chain_counts_weighted(states, sequence.windows(2).map(|pair| (pair[0], pair[1], 1.0)))
+}
+
+// Sum (from, to, weight) transitions into a count matrix; rows and columns follow `states`.
+// chain_counts is the unit-weight case over one sequence.
+pub fn chain_counts_weighted<S: ChainState>(
+    states: &[S],
+    transitions: impl IntoIterator<Item = (S, S, f64)>,
+) -> Result<Array2<f64>, String> {
+    let n = states.len();
+    let mut count_matrix = Array2::<f64>::zeros((n, n));
+    let index = |state: S| states.iter().position(|&s| s == state)
+        .ok_or_else(|| format!("State {} is not in the state list", state));
     
-    serde_wasm_bindgen::to_value(&statistics)
-        .map_err(|e| JsValue::from_str(&format!("Failed to serialize statistics: {}", e)))
+    for (from, to, weight) in transitions {
+        count_matrix[[index(from)?, index(to)?]] += weight;
+    }
+    Ok(count_matrix)
 }
 
-// Helper structures for serialization
+// Helper function counting weighted weather transitions in Sunny, Rainy, Cloudy order
+fn weather_counts_weighted(transitions: impl IntoIterator<Item = (StateType, StateType, f64)>) -> Array2<f64> {
+    // Every StateType is listed, so no entry can be missing
+    chain_counts_weighted(&TransitionMatrix::new().states, transitions).expect("all weather states are listed")
+}
 
-#[derive(Serialize, Deserialize)]
-struct MatrixData {
-    matrix: Vec<f64>,
-    states: Vec<String>,
-    rows: usize,
-    cols: usize,
+// Train a matrix over any state type from one observed sequence, the generic core of
+// build_transition_matrix: count, normalize (unseen states get uniform rows) and validate
+pub fn build_chain_matrix<S: ChainState>(states: Vec<S>, sequence: &[S]) -> Result<TransitionMatrix<S>, String> {
+    let count_matrix = chain_counts(&states, sequence)?;
+    let mut transition_matrix = counts_to_chain_matrix(states, &count_matrix);
+    repair_rows(&mut transition_matrix);
+    transition_matrix.validate_probabilities().map_err(|e| e.to_string())?;
+    Ok(transition_matrix)
 }
 
-#[derive(Serialize, Deserialize)]
-struct SimulationDay {
-    day: usize,
-    state: String,
-    timestamp: i64,
+// Count consecutive state transitions into a 3x3 matrix (Sunny, Rainy, Cloudy order)
+pub fn transition_counts(data: &HistoricalData) -> Array2<f64> {
+    let states = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+    let sequence: Vec<StateType> = data.iter().map(|w| w.state).collect();
+    
+    // Every StateType is listed, so no entry can be missing
+    chain_counts(&states, &sequence).expect("all weather states are listed")
 }
 
-#[derive(Serialize, Deserialize)]
-struct StateProbabilities {
-    sunny: f64,
-    rainy: f64,
-    cloudy: f64,
+// Train from soft observations: each consecutive pair adds the outer product of the two
+// days' probability vectors to the counts (treating the days' uncertainties as
+// independent), so a 70%-rainy day contributes 0.7 of a transition from Rainy.
+// With one-hot observations this matches build_transition_matrix.
+pub fn build_fuzzy_transition_matrix(observations: &[SoftObservation]) -> TransitionMatrix {
+    let mut count_matrix = Array2::<f64>::zeros((3, 3));
+    for pair in observations.windows(2) {
+        for (i, &p) in pair[0].probabilities.iter().enumerate() {
+            for (j, &q) in pair[1].probabilities.iter().enumerate() {
+                count_matrix[[i, j]] += p * q;
+            }
+        }
+    }
+    counts_to_matrix(&count_matrix)
 }
 
-#[derive(Serialize, Deserialize)]
-struct Statistics {
-    steady_state: StateProbabilities,
-    distribution: StateProbabilities,
-    average_streaks: StateProbabilities,
+// Normalize a count matrix into transition probabilities
+fn counts_to_matrix(count_matrix: &Array2<f64>) -> TransitionMatrix {
+    counts_to_chain_matrix(TransitionMatrix::new().states, count_matrix)
 }
 
-// Helper function to calculate state distribution from simulation results
-fn calculate_state_distribution(results: &[WeatherState]) -> Vec<f64> {
-    let total = results.len() as f64;
-    if total == 0.0 {
-        return vec![0.0, 0.0, 0.0];
+// Normalize a count matrix into transition probabilities over any states
+fn counts_to_chain_matrix<S: ChainState>(states: Vec<S>, count_matrix: &Array2<f64>) -> TransitionMatrix<S> {
+    let n = states.len();
+    let mut transition_matrix = TransitionMatrix { matrix: Array2::<f64>::zeros((n, n)), states, counts: None };
+    for i in 0..n {
+        normalize_count_row(count_matrix, &mut transition_matrix.matrix, i);
     }
+    transition_matrix.normalize_rows();
+    transition_matrix.counts = Some(count_matrix.clone());
+    transition_matrix
+}
+
+// Normalize one row of counts by its sum into the probability matrix
+fn normalize_count_row(count_matrix: &Array2<f64>, matrix: &mut Array2<f64>, i: usize) {
+    let n = count_matrix.ncols();
+    let row_sum: f64 = count_matrix.row(i).sum();
     
-    let mut counts = vec![0.0, 0.0, 0.0];
-    for state in results {
-        match state.state {
-            StateType::Sunny => counts[0] += 1.0,
-            StateType::Rainy => counts[1] += 1.0,
-            StateType::Cloudy => counts[2] += 1.0,
+    // If row sum is 0 (no transitions from this state), set uniform distribution
+    if row_sum > 0.0 {
+        for j in 0..n {
+            matrix[[i, j]] = count_matrix[[i, j]] / row_sum;
+        }
+    } else {
+        // Set uniform distribution (1/n for each state)
+        for j in 0..n {
+            matrix[[i, j]] = 1.0 / n as f64;
         }
     }
-    
-    counts.iter().map(|&c| c / total).collect()
 }
 
-// Helper function to calculate average streak lengths for each state
-fn calculate_average_streaks(results: &[WeatherState]) -> Vec<f64> {
-    if results.is_empty() {
-        return vec![0.0, 0.0, 0.0];
+// Transition model trained one observation at a time
+// Keeps raw counts (in matrix.counts) so each update only renormalizes one row, and
+// caches the last steady state so it can be refined cheaply instead of recomputed from scratch
+#[derive(Debug, Clone)]
+pub struct IncrementalModel {
+    matrix: TransitionMatrix,
+    last_state: Option<StateType>,
+    steady_state: Option<Vec<f64>>,
+}
+
+impl IncrementalModel {
+    pub fn new() -> Self {
+        Self {
+            matrix: counts_to_matrix(&Array2::<f64>::zeros((3, 3))),
+            last_state: None,
+            steady_state: None,
+        }
     }
-    
-    let states = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
-    let mut average_streaks = vec![0.0, 0.0, 0.0];
-    
-    for (idx, &target_state) in states.iter().enumerate() {
-        let mut streak_lengths = Vec::new();
-        let mut current_streak = 0;
+
+    // Seed the model with an existing history
+    pub fn from_data(data: &HistoricalData) -> Self {
+        Self {
+            matrix: counts_to_matrix(&transition_counts(data)),
+            last_state: data.states.last().map(|w| w.state),
+            steady_state: None,
+        }
+    }
+
+    // Record the next observed day, updating the transition from the previous day
+    pub fn update_with(&mut self, observation: &WeatherState) {
+        if let Some(previous) = self.last_state {
+            let from = self.matrix.state_index(previous).expect("trained matrices list every weather state");
+            let to = self.matrix.state_index(observation.state).expect("trained matrices list every weather state");
+            let counts = self.matrix.counts.as_mut().expect("trained matrices carry counts");
+            counts[[from, to]] += 1.0;
+            normalize_count_row(counts, &mut self.matrix.matrix, from);
+        }
+        self.last_state = Some(observation.state);
+    }
+
+    pub fn matrix(&self) -> &TransitionMatrix {
+        &self.matrix
+    }
+
+    pub fn counts(&self) -> &Array2<f64> {
+        self.matrix.counts.as_ref().expect("trained matrices carry counts")
+    }
+
+    // Steady state of the current matrix
+    // By default the cached vector from the previous call is refined with a short
+    // power iteration (pi <- pi * P), which converges in a handful of steps after a small
+    // update. The refinement stops once successive vectors differ by less than 1e-10 or
+    // after 100 steps, so slowly mixing or periodic chains may get an approximate answer.
+    // Pass force_exact to recompute from scratch with steady_state_auto.
+    pub fn steady_state(&mut self, force_exact: bool) -> Vec<f64> {
+        const MAX_REFINEMENT_STEPS: usize = 100;
+        const REFINEMENT_THRESHOLD: f64 = 1e-10;
         
-        for weather_state in results {
-            if weather_state.state == target_state {
-                current_streak += 1;
-            } else if current_streak > 0 {
-                streak_lengths.push(current_streak);
-                current_streak = 0;
+        let distribution = match (&self.steady_state, force_exact) {
+            (Some(cached), false) => {
+                let mut current = ndarray::Array1::from(cached.clone());
+                for _ in 0..MAX_REFINEMENT_STEPS {
+                    let next = current.dot(&self.matrix.matrix);
+                    let max_diff = next.iter().zip(current.iter())
+                        .map(|(a, b)| (a - b).abs())
+                        .fold(0.0, f64::max);
+                    current = next;
+                    if max_diff < REFINEMENT_THRESHOLD {
+                        break;
+                    }
+                }
+                current.to_vec()
             }
+            _ => steady_state_auto(&self.matrix).distribution,
+        };
+        
+        self.steady_state = Some(distribution.clone());
+        distribution
+    }
+}
+
+impl Default for IncrementalModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Second-order chain where tomorrow depends on both yesterday and today
+// Row prev * 3 + current of the 9x3 matrix holds P(next | prev, current), with states in
+// Sunny, Rainy, Cloudy order. Pairs never seen in the data fall back to the first-order
+// row for `current`, so sparse histories degrade to the plain model instead of uniform.
+#[derive(Debug, Clone)]
+pub struct SecondOrderModel {
+    pub matrix: Array2<f64>,
+}
+
+impl SecondOrderModel {
+    pub fn train(data: &HistoricalData) -> Self {
+        let first_order = build_transition_matrix(data);
+        let mut counts = Array2::<f64>::zeros((9, 3));
+        for window in data.states.windows(3) {
+            let row = Self::row_index(window[0].state, window[1].state);
+            counts[[row, Self::state_index(window[2].state)]] += 1.0;
         }
         
-        // Don't forget the last streak if it ends at the end of results
-        if current_streak > 0 {
-            streak_lengths.push(current_streak);
+        let mut matrix = Array2::<f64>::zeros((9, 3));
+        for (row, (mut probs, count_row)) in matrix.rows_mut().into_iter().zip(counts.rows()).enumerate() {
+            let total = count_row.sum();
+            if total > 0.0 {
+                probs.assign(&(&count_row / total));
+            } else {
+                probs.assign(&first_order.matrix.row(row % 3));
+            }
         }
+        Self { matrix }
+    }
+
+    // Exact per-day marginal distributions given the last two states, tracking the joint
+    // distribution over (yesterday, today) pairs. Day 0 is `current`, so the result has
+    // `days` entries like forecast_distribution.
+    pub fn forecast_distribution(&self, prev: StateType, current: StateType, days: usize) -> Vec<Vec<f64>> {
+        let mut joint = vec![0.0; 9];
+        joint[Self::row_index(prev, current)] = 1.0;
         
-        // Calculate average
-        if !streak_lengths.is_empty() {
-            let sum: usize = streak_lengths.iter().sum();
-            average_streaks[idx] = sum as f64 / streak_lengths.len() as f64;
+        let mut distributions = Vec::with_capacity(days);
+        for day in 0..days {
+            if day > 0 {
+                let mut next_joint = vec![0.0; 9];
+                for (row, &mass) in joint.iter().enumerate() {
+                    let today = row % 3;
+                    for (next, &p) in self.matrix.row(row).iter().enumerate() {
+                        next_joint[today * 3 + next] += mass * p;
+                    }
+                }
+                joint = next_joint;
+            }
+            let mut marginal = vec![0.0; 3];
+            for (row, &mass) in joint.iter().enumerate() {
+                marginal[row % 3] += mass;
+            }
+            distributions.push(marginal);
         }
+        distributions
+    }
+
+    fn state_index(state: StateType) -> usize {
+        match state {
+            StateType::Sunny => 0,
+            StateType::Rainy => 1,
+            StateType::Cloudy => 2,
+        }
+    }
+
+    fn row_index(prev: StateType, current: StateType) -> usize {
+        Self::state_index(prev) * 3 + Self::state_index(current)
     }
-    
-    average_streaks
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// Streaming forecaster over a rolling window of the most recent observations
+// Memory is bounded by the window: each new day evicts the oldest, the matrix is
+// retrained on what remains, and tomorrow's distribution is returned
+#[derive(Debug, Clone)]
+pub struct LiveForecaster {
+    window: std::collections::VecDeque<WeatherState>,
+    capacity: usize,
+    matrix: TransitionMatrix,
+}
 
-    #[test]
-    fn test_init() {
-        assert!(init_markov_engine().is_ok());
+impl LiveForecaster {
+    // Windows shorter than 2 days hold no transitions, so capacity is at least 2
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2);
+        Self {
+            window: std::collections::VecDeque::with_capacity(capacity),
+            capacity,
+            matrix: counts_to_matrix(&Array2::<f64>::zeros((3, 3))),
+        }
+    }
+
+    // Record a new day and return tomorrow's distribution (Sunny, Rainy, Cloudy order)
+    pub fn observe(&mut self, state: StateType, timestamp: i64) -> Vec<f64> {
+        if self.window.len() == self.capacity {
+            self.window.pop_front();
+        }
+        self.window.push_back(WeatherState::new(state, timestamp));
+        
+        let mut data = HistoricalData::new(String::new());
+        data.states = self.window.iter().cloned().collect();
+        self.matrix = build_transition_matrix(&data);
+        
+        next_state_distribution(&self.matrix, state).expect("trained matrices list every weather state")
+    }
+
+    pub fn matrix(&self) -> &TransitionMatrix {
+        &self.matrix
+    }
+
+    pub fn window(&self) -> impl Iterator<Item = &WeatherState> {
+        self.window.iter()
+    }
+}
+
+// Simulate weather using probabilistic sampling; simulate_chain plus daily timestamps
+pub fn simulate_weather(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+) -> Vec<WeatherState> {
+    simulate_weather_with_rng(matrix, initial_state, days, &mut OsRandom)
+}
+
+// Simulate weather drawing random numbers from the given source
+// Panics if the matrix doesn't list initial_state or the source fails; use
+// try_simulate_weather_with_rng to handle either
+pub fn simulate_weather_with_rng(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    rng: &mut impl RandomSource,
+) -> Vec<WeatherState> {
+    try_simulate_weather_with_rng(matrix, initial_state, days, rng)
+        .unwrap_or_else(|e| panic!("Simulation failed: {}", e))
+}
+
+// Simulate a "typical" run unconditioned on any starting day: the day-0 state is drawn
+// from the steady-state distribution, then the chain runs as usual on the same seeded stream
+pub fn simulate_from_steady_state(matrix: &TransitionMatrix, days: usize, seed: u64) -> Vec<WeatherState> {
+    let mut rng = SeededRandom::new(seed);
+    let steady_state = steady_state_auto(matrix).distribution;
+    let initial_state = weighted_random_sample(&matrix.states, &steady_state, &mut rng);
+    simulate_weather_with_rng(matrix, initial_state, days, &mut rng)
+}
+
+// Simulate weather, reporting an unknown initial state or random number failures
+// instead of panicking
+pub fn try_simulate_weather_with_rng(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    rng: &mut impl RandomSource,
+) -> Result<Vec<WeatherState>, SimulationError> {
+    // Run the generic chain, then stamp each state with its day
+    let states = simulate_chain(matrix, initial_state, days, rng)?;
+    Ok(states.into_iter()
+        .enumerate()
+        .map(|(day, state)| WeatherState::new(state, day as i64 * 86400))
+        .collect())
+}
+
+// Simulate any chain for `steps` states, starting with `initial` (always included, so
+// even steps = 0 yields it); each next state is sampled from the current state's row.
+// Errors if the matrix doesn't list `initial`.
+pub fn simulate_chain<S: ChainState>(
+    matrix: &TransitionMatrix<S>,
+    initial: S,
+    steps: usize,
+    rng: &mut impl RandomSource,
+) -> Result<Vec<S>, SimulationError> {
+    matrix.require_state(initial).map_err(SimulationError::UnknownState)?;
+    let mut results = Vec::with_capacity(steps.max(1));
+    results.push(initial);
+    
+    let mut current_state = initial;
+    for _ in 1..steps {
+        // Get current state's transition probabilities; sampled states are always listed
+        let current_idx = matrix.state_index(current_state).expect("checked above or sampled from matrix.states");
+        let probabilities = matrix.matrix.row(current_idx).to_vec();
+        
+        // Use weighted random sampling to select next state based on probabilities
+        let next_state = matrix.states[try_sample_index(&probabilities, rng)?];
+        results.push(next_state);
+        current_state = next_state;
+    }
+    
+    Ok(results)
+}
+
+// Run `runs` independent seeded simulations from the same start
+pub fn simulate_ensemble(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    runs: usize,
+    seed: u64,
+) -> Vec<Vec<WeatherState>> {
+    let mut rng = SeededRandom::new(seed);
+    (0..runs)
+        .map(|_| simulate_weather_with_rng(matrix, initial_state, days, &mut rng))
+        .collect()
+}
+
+// Per-day state frequencies across an ensemble of equal-length paths
+// Entry [day][s] is the fraction of paths in state s (matrix order) on that day; errors
+// if the paths differ in length or visit a state the matrix doesn't list
+pub fn ensemble_distribution(matrix: &TransitionMatrix, paths: &[Vec<WeatherState>]) -> Result<Vec<Vec<f64>>, String> {
+    let n = matrix.states.len();
+    let days = paths.first().map_or(0, |path| path.len());
+    if let Some(path) = paths.iter().find(|path| path.len() != days) {
+        return Err(format!("Ensemble paths must share one length: found {} and {} days", days, path.len()));
+    }
+    let mut distribution = vec![vec![0.0; n]; days];
+    
+    for path in paths {
+        for (day, weather_state) in path.iter().enumerate() {
+            distribution[day][matrix.require_state(weather_state.state)?] += 1.0;
+        }
+    }
+    for day in &mut distribution {
+        for p in day.iter_mut() {
+            *p /= paths.len() as f64;
+        }
+    }
+    Ok(distribution)
+}
+
+// Index of the most typical path in an ensemble: the one whose summed day-by-day
+// state_distance to every other path is smallest (ties go to the earlier path).
+// Paths of different lengths are compared over their shared days; None if empty.
+pub fn ensemble_medoid(paths: &[Vec<WeatherState>]) -> Option<usize> {
+    let path_distance = |a: &[WeatherState], b: &[WeatherState]| -> f64 {
+        a.iter().zip(b.iter()).map(|(x, y)| state_distance(x.state, y.state)).sum()
+    };
+    
+    let mut best: Option<(usize, f64)> = None;
+    for (i, path) in paths.iter().enumerate() {
+        let total: f64 = paths.iter().map(|other| path_distance(path, other)).sum();
+        if best.is_none_or(|(_, best_total)| total < best_total) {
+            best = Some((i, total));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+// Per-day state distribution from `pairs` antithetic pairs (2 * pairs runs in total)
+// Each pair drives one run with uniforms u and its mirror with 1 - u, so their sampling
+// errors partly cancel and the estimate has lower variance than simulate_ensemble with
+// the same number of runs
+pub fn simulate_ensemble_antithetic(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    pairs: usize,
+    seed: u64,
+) -> Vec<Vec<f64>> {
+    let mut rng = SeededRandom::new(seed);
+    let mut paths = Vec::with_capacity(pairs * 2);
+    
+    for _ in 0..pairs {
+        // Day 0 is fixed, so each run consumes one uniform per later day
+        let draws: Vec<f64> = (1..days).map(|_| rng.next_f64()).collect();
+        let mut forward = ReplayRandom(draws.iter().copied());
+        let mut mirror = ReplayRandom(draws.iter().map(|u| 1.0 - u));
+        paths.push(simulate_weather_with_rng(matrix, initial_state, days, &mut forward));
+        paths.push(simulate_weather_with_rng(matrix, initial_state, days, &mut mirror));
+    }
+    
+    ensemble_distribution(matrix, &paths).expect("simulated paths only visit matrix states")
+}
+
+// Monte Carlo summary of the longest run of one state within a horizon
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MaxStreakEstimate {
+    pub mean: f64,
+    // 95th percentile of the per-run maximum, showing the tail risk
+    pub percentile_95: usize,
+}
+
+// Expected longest uninterrupted run of `state` over `days` days (day 0 included),
+// e.g. the longest dry spell this month. There's no simple closed form, so this averages
+// the maximum over `runs` seeded simulations; around 1,000 runs gives a stable mean.
+pub fn expected_max_streak(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    state: StateType,
+    days: usize,
+    runs: usize,
+    seed: u64,
+) -> MaxStreakEstimate {
+    let mut maxima: Vec<usize> = simulate_ensemble(matrix, initial_state, days, runs, seed)
+        .iter()
+        .map(|path| {
+            let mut longest = 0;
+            let mut current = 0;
+            for weather_state in path {
+                current = if weather_state.state == state { current + 1 } else { 0 };
+                longest = longest.max(current);
+            }
+            longest
+        })
+        .collect();
+    
+    if maxima.is_empty() {
+        return MaxStreakEstimate { mean: 0.0, percentile_95: 0 };
+    }
+    maxima.sort_unstable();
+    
+    // Nearest-rank percentile
+    let rank = (0.95 * maxima.len() as f64).ceil() as usize;
+    MaxStreakEstimate {
+        mean: maxima.iter().sum::<usize>() as f64 / maxima.len() as f64,
+        percentile_95: maxima[rank.clamp(1, maxima.len()) - 1],
+    }
+}
+
+// Monte Carlo histogram of how many days each state occupies over the horizon
+// histogram[s][k] is the number of runs in which state s (matrix order) occurred on
+// exactly k of the `days` days (day 0 included)
+pub fn count_histogram(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    runs: usize,
+    seed: u64,
+) -> Vec<Vec<usize>> {
+    let n = matrix.states.len();
+    let mut histogram = vec![vec![0usize; days + 1]; n];
+    
+    for path in simulate_ensemble(matrix, initial_state, days, runs, seed) {
+        let mut counts = vec![0usize; n];
+        for weather_state in &path {
+            counts[matrix.state_index(weather_state.state).expect("simulated paths only visit matrix states")] += 1;
+        }
+        for (state_idx, &count) in counts.iter().enumerate() {
+            histogram[state_idx][count] += 1;
+        }
+    }
+    
+    histogram
+}
+
+// Lazy, open-ended simulation: yields day 0 (the initial state) and then one sampled
+// day per call, so consumers can take(n) or stream days without precomputing a Vec
+pub struct SimulationIter<'a, R: RandomSource = OsRandom> {
+    matrix: &'a TransitionMatrix,
+    current: Option<StateType>,
+    initial_state: StateType,
+    day: i64,
+    rng: R,
+}
+
+impl<'a> SimulationIter<'a, OsRandom> {
+    pub fn new(matrix: &'a TransitionMatrix, initial_state: StateType) -> Result<Self, String> {
+        Self::with_rng(matrix, initial_state, OsRandom)
+    }
+}
+
+impl<'a, R: RandomSource> SimulationIter<'a, R> {
+    // Errors up front if the initial state isn't in the matrix; every later day is
+    // sampled from matrix.states, so the iterator itself never fails
+    pub fn with_rng(matrix: &'a TransitionMatrix, initial_state: StateType, rng: R) -> Result<Self, String> {
+        matrix.require_state(initial_state)?;
+        Ok(Self {
+            matrix,
+            current: None,
+            initial_state,
+            day: 0,
+            rng,
+        })
+    }
+}
+
+impl<R: RandomSource> Iterator for SimulationIter<'_, R> {
+    type Item = WeatherState;
+
+    fn next(&mut self) -> Option<WeatherState> {
+        let next_state = match self.current {
+            None => self.initial_state,
+            Some(current_state) => {
+                let current_idx = self.matrix.state_index(current_state).expect("checked in with_rng");
+                let probabilities = self.matrix.matrix.row(current_idx).to_vec();
+                weighted_random_sample(&self.matrix.states, &probabilities, &mut self.rng)
+            }
+        };
+        
+        let weather_state = WeatherState::new(next_state, self.day * 86400);
+        self.current = Some(next_state);
+        self.day += 1;
+        Some(weather_state)
+    }
+}
+
+// Simulate while nudging each step toward a fixed target distribution (e.g. monthly climatology)
+// Each row is blended as (1 - bias_weight) * P[current] + bias_weight * bias_dist and
+// re-normalized before sampling; bias_weight = 0 is the plain chain
+pub fn simulate_with_bias(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    bias_dist: &[f64],
+    bias_weight: f64,
+    rng: &mut impl RandomSource,
+) -> Result<Vec<WeatherState>, String> {
+    let n = matrix.states.len();
+    if bias_dist.len() != n {
+        return Err(format!("Bias distribution has {} entries, expected {}", bias_dist.len(), n));
+    }
+    if bias_dist.iter().any(|&p| !p.is_finite() || p < 0.0) {
+        return Err("Bias distribution entries must be finite and non-negative".to_string());
+    }
+    if !(0.0..=1.0).contains(&bias_weight) {
+        return Err(format!("Bias weight must be between 0 and 1, got {}", bias_weight));
+    }
+    matrix.require_state(initial_state)?;
+    
+    let mut results = Vec::with_capacity(days);
+    results.push(WeatherState::new(initial_state, 0));
+    let mut current_state = initial_state;
+    
+    for day in 1..days {
+        let current_idx = matrix.require_state(current_state)?;
+        let mut blended: Vec<f64> = matrix.matrix.row(current_idx).iter()
+            .zip(bias_dist.iter())
+            .map(|(&p, &b)| (1.0 - bias_weight) * p + bias_weight * b)
+            .collect();
+        
+        // Re-normalize so the blended row is a valid distribution
+        let sum: f64 = blended.iter().sum();
+        if sum <= 0.0 {
+            return Err(format!("Blended transition row for {} has no probability mass", current_state));
+        }
+        blended.iter_mut().for_each(|p| *p /= sum);
+        
+        let next_state = matrix.states[try_sample_index(&blended, rng).map_err(|e| e.to_string())?];
+        results.push(WeatherState::new(next_state, day as i64 * 86400));
+        current_state = next_state;
+    }
+    
+    Ok(results)
+}
+
+// A simulation that may have stopped early because the chain got stuck
+#[derive(Debug, Clone)]
+pub struct SimulationOutcome {
+    pub results: Vec<WeatherState>,
+    // Day the chain entered the absorbing state it got stuck in, if it stopped early
+    pub absorbed_at: Option<usize>,
+}
+
+// Opt-in variant of simulate_weather that stops once the last `stuck_days` days are all the
+// same state and that state's self-loop probability is effectively 1, instead of emitting
+// a flat line up to `days`. The returned results end with the `stuck_days` identical days.
+pub fn simulate_until_absorbed(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    stuck_days: usize,
+    rng: &mut impl RandomSource,
+) -> Result<SimulationOutcome, String> {
+    const ABSORBING_TOLERANCE: f64 = 1e-9;
+    
+    if stuck_days == 0 {
+        return Err("Stuck-day threshold must be at least 1 day".to_string());
+    }
+    
+    let mut results = Vec::with_capacity(days);
+    results.push(WeatherState::new(initial_state, 0));
+    let mut current_state = initial_state;
+    let mut streak = 1;
+    
+    for day in 1..days {
+        let current_idx = matrix.require_state(current_state)?;
+        if streak >= stuck_days && matrix.matrix[[current_idx, current_idx]] >= 1.0 - ABSORBING_TOLERANCE {
+            return Ok(SimulationOutcome { results, absorbed_at: Some(day - streak) });
+        }
+        
+        let probabilities = matrix.matrix.row(current_idx).to_vec();
+        let next_state = matrix.states[try_sample_index(&probabilities, rng).map_err(|e| e.to_string())?];
+        streak = if next_state == current_state { streak + 1 } else { 1 };
+        results.push(WeatherState::new(next_state, day as i64 * 86400));
+        current_state = next_state;
+    }
+    
+    Ok(SimulationOutcome { results, absorbed_at: None })
+}
+
+// Simulate without ever letting one state run longer than `max_streak` days (day 0
+// counts). Once the current state has held for `max_streak` days the next step is
+// forced away from it, sampling from its off-diagonal probabilities re-normalized
+// (uniformly over the other states if it never leaves). This is a realism heuristic:
+// it biases the output away from the pure chain and is not a correct probabilistic model.
+pub fn simulate_capped(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    max_streak: usize,
+    rng: &mut impl RandomSource,
+) -> Result<Vec<WeatherState>, String> {
+    if max_streak == 0 {
+        return Err("Maximum streak must be at least 1 day".to_string());
+    }
+    if matrix.states.len() < 2 && days > max_streak {
+        return Err("Cannot cap streaks with a single state".to_string());
+    }
+    
+    let mut results = Vec::with_capacity(days);
+    results.push(WeatherState::new(initial_state, 0));
+    let mut current_state = initial_state;
+    let mut streak = 1;
+    
+    for day in 1..days {
+        let current_idx = matrix.require_state(current_state)?;
+        let mut probabilities = matrix.matrix.row(current_idx).to_vec();
+        
+        if streak >= max_streak {
+            probabilities[current_idx] = 0.0;
+            let sum: f64 = probabilities.iter().sum();
+            let others = (probabilities.len() - 1) as f64;
+            for (i, p) in probabilities.iter_mut().enumerate() {
+                *p = if i == current_idx {
+                    0.0
+                } else if sum > 0.0 {
+                    *p / sum
+                } else {
+                    1.0 / others
+                };
+            }
+        }
+        
+        let next_state = matrix.states[try_sample_index(&probabilities, rng).map_err(|e| e.to_string())?];
+        streak = if next_state == current_state { streak + 1 } else { 1 };
+        results.push(WeatherState::new(next_state, day as i64 * 86400));
+        current_state = next_state;
+    }
+    
+    Ok(results)
+}
+
+// Simulate weather with timestamps counting whole days from a UTC start timestamp
+pub fn simulate_weather_dated(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    start_timestamp: i64,
+) -> Result<Vec<WeatherState>, SimulationError> {
+    let mut results = try_simulate_weather_with_rng(matrix, initial_state, days, &mut OsRandom)?;
+    for weather_state in results.iter_mut() {
+        weather_state.timestamp += start_timestamp;
+    }
+    Ok(results)
+}
+
+// Error from a random source that could not produce a value
+#[derive(Debug, Clone)]
+pub struct RandomError(pub String);
+
+impl fmt::Display for RandomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Random number generation unavailable: {}", self.0)
+    }
+}
+
+impl std::error::Error for RandomError {}
+
+// Why a simulation could not run
+#[derive(Debug, Clone)]
+pub enum SimulationError {
+    // The initial state is not one of the matrix's states
+    UnknownState(String),
+    Random(RandomError),
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::UnknownState(msg) => write!(f, "{}", msg),
+            SimulationError::Random(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+impl From<RandomError> for SimulationError {
+    fn from(e: RandomError) -> Self {
+        SimulationError::Random(e)
+    }
+}
+
+// Source of uniformly distributed random numbers in [0, 1]
+pub trait RandomSource {
+    fn next_f64(&mut self) -> f64;
+
+    // Fallible variant for sources that can run out of entropy; infallible sources
+    // can rely on the default
+    fn try_next_f64(&mut self) -> Result<f64, RandomError> {
+        Ok(self.next_f64())
+    }
+}
+
+// Entropy from the OS (crypto.getRandomValues in the browser)
+// Locked-down runtimes may have no entropy source; use try_next_f64 to handle that
+pub struct OsRandom;
+
+impl RandomSource for OsRandom {
+    fn next_f64(&mut self) -> f64 {
+        self.try_next_f64().expect("Failed to generate random number")
+    }
+
+    fn try_next_f64(&mut self) -> Result<f64, RandomError> {
+        let mut buf = [0u8; 8];
+        getrandom::getrandom(&mut buf).map_err(|e| RandomError(e.to_string()))?;
+        Ok(u64::from_le_bytes(buf) as f64 / u64::MAX as f64)
+    }
+}
+
+// Deterministic SplitMix64 generator for reproducible runs
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_f64(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // Use the top 53 bits for a uniformly spaced f64
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Replays a fixed sequence of uniform values, used to pair antithetic runs
+struct ReplayRandom<I: Iterator<Item = f64>>(I);
+
+impl<I: Iterator<Item = f64>> RandomSource for ReplayRandom<I> {
+    fn next_f64(&mut self) -> f64 {
+        self.0.next().expect("Replay sequence exhausted")
+    }
+}
+
+// Pick an index with probability proportional to probs using cumulative sampling
+pub fn sample_index(probs: &[f64], rng: &mut impl RandomSource) -> usize {
+    index_for_value(probs, rng.next_f64())
+}
+
+// Fallible sample_index for random sources that may fail
+pub fn try_sample_index(probs: &[f64], rng: &mut impl RandomSource) -> Result<usize, RandomError> {
+    Ok(index_for_value(probs, rng.try_next_f64()?))
+}
+
+// Helper function mapping a uniform value to an index via cumulative probabilities
+fn index_for_value(probs: &[f64], random_value: f64) -> usize {
+    // Use cumulative probabilities to select an index
+    let mut cumulative = 0.0;
+    for (i, &prob) in probs.iter().enumerate() {
+        cumulative += prob;
+        if random_value <= cumulative {
+            return i;
+        }
+    }
+    
+    // Fallback to last index (should not happen with valid probabilities)
+    probs.len() - 1
+}
+
+// Helper function for weighted random sampling
+fn weighted_random_sample<S: Copy>(states: &[S], probabilities: &[f64], rng: &mut impl RandomSource) -> S {
+    states[sample_index(probabilities, rng)]
+}
+
+// Serialize simulation results as CSV with a header row
+// Columns: day index, state name, raw timestamp and the timestamp as an ISO date
+pub fn simulation_to_csv(results: &[WeatherState]) -> String {
+    simulation_to_csv_with_offset(results, 0)
+}
+
+// Same as simulation_to_csv, but shifts every emitted timestamp by a fixed UTC offset
+// so the date column shows local calendar days. No DST handling is attempted.
+pub fn simulation_to_csv_with_offset(results: &[WeatherState], utc_offset_secs: i64) -> String {
+    let mut csv = String::from("day,state,timestamp,date\n");
+    for (day, weather_state) in results.iter().enumerate() {
+        let timestamp = weather_state.timestamp + utc_offset_secs;
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            day,
+            weather_state.state,
+            timestamp,
+            timestamp_to_iso_date(timestamp)
+        ));
+    }
+    csv
+}
+
+// Render the chain as a Mermaid stateDiagram-v2 for pasting into markdown
+// Every state is declared even if all its edges are filtered out; only transitions
+// with probability strictly above `min_prob` are drawn, self-loops included
+pub fn to_mermaid(matrix: &TransitionMatrix, min_prob: f64) -> String {
+    let mut diagram = String::from("stateDiagram-v2\n");
+    for state in &matrix.states {
+        diagram.push_str(&format!("    state {}\n", state));
+    }
+    for (i, from) in matrix.states.iter().enumerate() {
+        for (j, to) in matrix.states.iter().enumerate() {
+            let p = matrix.matrix[[i, j]];
+            if p > min_prob {
+                diagram.push_str(&format!("    {} --> {}: {:.2}\n", from, to, p));
+            }
+        }
+    }
+    diagram
+}
+
+// n-step transition matrix P^n; n = 0 gives the identity
+pub fn n_step_matrix(matrix: &TransitionMatrix, n: usize) -> TransitionMatrix {
+    let mut result = TransitionMatrix::identity(matrix.states.clone());
+    for _ in 0..n {
+        result.matrix = result.matrix.dot(&matrix.matrix);
+    }
+    result
+}
+
+// How many observed from -> to transitions back P[from, to], e.g. to show
+// "Sunny -> Rainy: 12% (based on 3 observations)". None when the matrix carries no
+// counts because it was imported, hand-entered or derived rather than trained. Counts
+// from reliability-weighted or soft training can be fractional and are returned as is.
+pub fn transition_support(matrix: &TransitionMatrix, from: StateType, to: StateType) -> Option<f64> {
+    let counts = matrix.counts.as_ref()?;
+    let i = matrix.state_index(from)?;
+    let j = matrix.state_index(to)?;
+    Some(counts[[i, j]])
+}
+
+// Tomorrow's state probabilities given today, ordered like matrix.states
+pub fn next_state_distribution(matrix: &TransitionMatrix, current: StateType) -> Result<Vec<f64>, String> {
+    Ok(matrix.matrix.row(matrix.require_state(current)?).to_vec())
+}
+
+// Probability that a run from `initial_state` matches a partial pattern, where
+// pattern[k] constrains day k (day 0 is the initial state, as in forecast_distribution)
+// and None means any state. Propagates the distribution forward, zeroing the disallowed
+// states on each constrained day, so every path consistent with the mask is summed.
+// Requiring a state the matrix doesn't list gives 0; an unknown initial state is an error.
+pub fn pattern_probability(matrix: &TransitionMatrix, initial_state: StateType, pattern: &[Option<StateType>]) -> Result<f64, String> {
+    let n = matrix.matrix.nrows();
+    let mut current = ndarray::Array1::<f64>::zeros(n);
+    current[matrix.require_state(initial_state)?] = 1.0;
+    
+    for (day, constraint) in pattern.iter().enumerate() {
+        if day > 0 {
+            current = current.dot(&matrix.matrix);
+        }
+        if let Some(required) = constraint {
+            let allowed = matrix.state_index(*required);
+            for (i, p) in current.iter_mut().enumerate() {
+                if Some(i) != allowed {
+                    *p = 0.0;
+                }
+            }
+        }
+    }
+    Ok(current.sum())
+}
+
+// Exact per-day state distributions, propagating a one-hot initial state through the matrix
+// Day 0 is the initial state, so the result has `days` entries like simulate_weather
+pub fn forecast_distribution(matrix: &TransitionMatrix, initial_state: StateType, days: usize) -> Result<Vec<Vec<f64>>, String> {
+    Ok(forecast_from_index(matrix, matrix.require_state(initial_state)?, days))
+}
+
+// Helper function propagating a one-hot start at a known state index
+fn forecast_from_index(matrix: &TransitionMatrix, start: usize, days: usize) -> Vec<Vec<f64>> {
+    let n = matrix.matrix.nrows();
+    let mut current = ndarray::Array1::<f64>::zeros(n);
+    current[start] = 1.0;
+    
+    let mut distributions = Vec::with_capacity(days);
+    for day in 0..days {
+        if day > 0 {
+            current = current.dot(&matrix.matrix);
+        }
+        distributions.push(current.to_vec());
+    }
+    distributions
+}
+
+// Shannon entropy in bits of a single distribution: -sum p * log2(p), skipping zeros
+pub fn distribution_entropy(dist: &[f64]) -> f64 {
+    dist.iter()
+        .filter(|&&p| p > 0.0)
+        .map(|&p| -p * p.log2())
+        .sum()
+}
+
+// Entropy in bits of each forecast day's exact distribution, showing how confidence decays
+// Starts at 0 on day 0 (today is known) and approaches the steady-state entropy
+pub fn entropy_trajectory(matrix: &TransitionMatrix, initial_state: StateType, days: usize) -> Result<Vec<f64>, String> {
+    Ok(forecast_distribution(matrix, initial_state, days)?
+        .iter()
+        .map(|dist| distribution_entropy(dist))
+        .collect())
+}
+
+// forecast_distribution as self-describing days: each carries its timestamp (whole days
+// from `start_timestamp`, like simulate_weather_dated), labeled probabilities and entropy
+pub fn forecast_days(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    start_timestamp: i64,
+) -> Result<Vec<ForecastDay>, String> {
+    Ok(forecast_distribution(matrix, initial_state, days)?
+        .iter()
+        .enumerate()
+        .map(|(day, dist)| ForecastDay {
+            day,
+            timestamp: start_timestamp + day as i64 * 86400,
+            distribution: StateProbabilities::from_states(&matrix.states, dist),
+            entropy: distribution_entropy(dist),
+        })
+        .collect())
+}
+
+// Calculate steady-state distribution using power iteration method
+pub fn calculate_steady_state<S: ChainState>(matrix: &TransitionMatrix<S>) -> Vec<f64> {
+    power_iteration(matrix).0
+}
+
+// Power iteration returning the first row of P^n, whether it converged, and how many
+// matrix multiplications it took
+fn power_iteration<S: ChainState>(matrix: &TransitionMatrix<S>) -> (Vec<f64>, bool, usize) {
+    const MAX_ITERATIONS: usize = 1000;
+    const CONVERGENCE_THRESHOLD: f64 = 1e-8;
+    
+    let n = matrix.matrix.nrows();
+    let mut current_matrix = matrix.matrix.clone();
+    let mut previous_matrix = matrix.matrix.clone();
+    
+    // Use power iteration method: multiply matrix by itself repeatedly
+    for iteration in 0..MAX_ITERATIONS {
+        // Multiply matrix by itself
+        current_matrix = current_matrix.dot(&matrix.matrix);
+        
+        // Check for convergence when successive iterations differ by less than threshold
+        if iteration > 0 {
+            let mut max_diff = 0.0;
+            for i in 0..n {
+                for j in 0..n {
+                    let diff = (current_matrix[[i, j]] - previous_matrix[[i, j]]).abs();
+                    if diff > max_diff {
+                        max_diff = diff;
+                    }
+                }
+            }
+            
+            // If converged, extract stationary distribution
+            if max_diff < CONVERGENCE_THRESHOLD {
+                // Extract the first row (all rows should be identical at steady state)
+                return (clean_distribution(current_matrix.row(0).to_vec()), true, iteration + 1);
+            }
+        }
+        
+        previous_matrix = current_matrix.clone();
+    }
+    
+    // If we didn't converge, return the current approximation
+    // Extract stationary distribution from converged matrix (first row)
+    (clean_distribution(current_matrix.row(0).to_vec()), false, MAX_ITERATIONS)
+}
+
+// Helper function turning a numerically computed steady state into a proper distribution:
+// round-off can leave tiny negative entries or a total slightly off 1.0, so negatives
+// (and NaN) are clamped to zero and the rest rescaled. No mass at all becomes uniform.
+fn clean_distribution(mut distribution: Vec<f64>) -> Vec<f64> {
+    for p in distribution.iter_mut() {
+        if p.is_nan() || *p < 0.0 {
+            *p = 0.0;
+        }
+    }
+    let sum: f64 = distribution.iter().sum();
+    let n = distribution.len() as f64;
+    for p in distribution.iter_mut() {
+        *p = if sum > 0.0 { *p / sum } else { 1.0 / n };
+    }
+    distribution
+}
+
+// Cesàro (time-averaged) limiting distribution: the first row of (1/n) * sum_{k=1..n} P^k
+// Converges even for periodic chains where P^n itself oscillates; for aperiodic
+// irreducible chains it coincides with the stationary distribution. n = 0 is treated as 1.
+pub fn cesaro_limit(matrix: &TransitionMatrix, n: usize) -> Vec<f64> {
+    let n = n.max(1);
+    let mut power = matrix.matrix.clone();
+    let mut sum = matrix.matrix.row(0).to_owned();
+    for _ in 1..n {
+        power = power.dot(&matrix.matrix);
+        sum += &power.row(0);
+    }
+    clean_distribution(sum.iter().map(|&p| p / n as f64).collect())
+}
+
+// Solve pi * P = pi with sum(pi) = 1 exactly as a linear system
+// Returns None when the system is singular (e.g. several closed classes)
+pub fn steady_state_exact(matrix: &TransitionMatrix) -> Option<Vec<f64>> {
+    let n = matrix.matrix.nrows();
+    
+    // Rows of (P^T - I), with the last equation replaced by the normalization constraint
+    let mut system = matrix.matrix.t().to_owned();
+    for i in 0..n {
+        system[[i, i]] -= 1.0;
+    }
+    system.row_mut(n - 1).fill(1.0);
+    let mut rhs = vec![0.0; n];
+    rhs[n - 1] = 1.0;
+    
+    solve_linear_system(system, rhs).map(clean_distribution)
+}
+
+// Stationary distribution as the left eigenvector of P for eigenvalue 1, i.e. the null
+// space of P^T - I, normalized to sum to 1. For three states the null vector is the
+// cross product of two independent rows of P^T - I (the cofactor trick); the pair with the
+// largest cross product is used for stability. Errors when eigenvalue 1 is repeated
+// (several closed classes), since the stationary distribution is then not unique.
+pub fn stationary_eigenvector(matrix: &TransitionMatrix) -> Result<Vec<f64>, String> {
+    const NULL_SPACE_EPSILON: f64 = 1e-12;
+    
+    let n = matrix.matrix.nrows();
+    let mut system = matrix.matrix.t().to_owned();
+    for i in 0..n {
+        system[[i, i]] -= 1.0;
+    }
+    
+    // Candidate null vectors, one per pair of rows (or per row for two states)
+    let candidates: Vec<Vec<f64>> = match n {
+        1 => vec![vec![1.0]],
+        2 => system.rows().into_iter().map(|r| vec![r[1], -r[0]]).collect(),
+        3 => [(0, 1), (0, 2), (1, 2)].iter().map(|&(a, b)| {
+            let (u, v) = (system.row(a), system.row(b));
+            vec![
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ]
+        }).collect(),
+        _ => return Err(format!("Eigenvector method supports up to 3 states, got {}", n)),
+    };
+    let null_vector = candidates.into_iter()
+        .max_by(|a, b| {
+            let norm = |v: &Vec<f64>| v.iter().map(|x| x * x).sum::<f64>();
+            norm(a).total_cmp(&norm(b))
+        })
+        .unwrap();
+    
+    let sum: f64 = null_vector.iter().sum();
+    if null_vector.iter().all(|x| x.abs() < NULL_SPACE_EPSILON) || sum.abs() < NULL_SPACE_EPSILON {
+        return Err("Eigenvalue 1 is repeated; the chain is reducible and has no unique stationary distribution".to_string());
+    }
+    Ok(clean_distribution(null_vector.iter().map(|x| x / sum).collect()))
+}
+
+// Transitive closure of the positive-probability transition graph
+// reachable[[i, j]] is true when j can be reached from i in one or more steps
+fn reachability(matrix: &TransitionMatrix) -> Array2<bool> {
+    let n = matrix.matrix.nrows();
+    let mut reachable = matrix.matrix.mapv(|p| p > 0.0);
+    
+    for k in 0..n {
+        for i in 0..n {
+            if reachable[[i, k]] {
+                for j in 0..n {
+                    if reachable[[k, j]] {
+                        reachable[[i, j]] = true;
+                    }
+                }
+            }
+        }
+    }
+    
+    reachable
+}
+
+// A chain is irreducible when every state can reach every other state
+pub fn is_irreducible(matrix: &TransitionMatrix) -> bool {
+    reachability(matrix)
+        .indexed_iter()
+        .all(|((i, j), &r)| r || i == j)
+}
+
+// Greatest common divisor, with gcd(0, b) = b
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// Period of a single state: gcd of the lengths of all closed walks through it
+// Returns 0 when the chain can never return to the state
+pub fn state_period(matrix: &TransitionMatrix, state: StateType) -> Result<usize, String> {
+    Ok(period_at(matrix, matrix.require_state(state)?))
+}
+
+// Helper function computing the period of the state at a known index
+fn period_at(matrix: &TransitionMatrix, start: usize) -> usize {
+    let n = matrix.matrix.nrows();
+    let reachable = reachability(matrix);
+    if !reachable[[start, start]] {
+        return 0;
+    }
+    
+    // Restrict to the communicating class of the start state
+    let in_class: Vec<bool> = (0..n)
+        .map(|j| j == start || (reachable[[start, j]] && reachable[[j, start]]))
+        .collect();
+    
+    // Breadth-first levels from the start state within its class
+    let mut level: Vec<Option<usize>> = vec![None; n];
+    level[start] = Some(0);
+    let mut queue = std::collections::VecDeque::from([start]);
+    while let Some(u) = queue.pop_front() {
+        for v in 0..n {
+            if in_class[v] && level[v].is_none() && matrix.matrix[[u, v]] > 0.0 {
+                level[v] = Some(level[u].unwrap() + 1);
+                queue.push_back(v);
+            }
+        }
+    }
+    
+    // Every edge u -> v inside the class closes a cycle of length level[u] + 1 - level[v]
+    let mut period = 0;
+    for u in (0..n).filter(|&u| in_class[u]) {
+        for v in (0..n).filter(|&v| in_class[v]) {
+            if matrix.matrix[[u, v]] > 0.0 {
+                let cycle = (level[u].unwrap() + 1).abs_diff(level[v].unwrap());
+                period = gcd(period, cycle);
+            }
+        }
+    }
+    
+    period
+}
+
+// Period of the chain: 1 for an aperiodic chain, d > 1 when states strictly oscillate
+// For reducible chains this is the largest period among the communicating classes,
+// since any periodic class prevents P^n from converging
+pub fn period(matrix: &TransitionMatrix) -> usize {
+    (0..matrix.states.len())
+        .map(|start| period_at(matrix, start))
+        .max()
+        .unwrap_or(0)
+        .max(1)
+}
+
+// Long-run distribution from each starting state: row i is where row i of P^n ends up.
+// Irreducible chains give identical rows; reducible ones differ by which closed classes
+// each start can fall into. Computed by squaring up to P^(2^20) so transients die out,
+// then averaging over one full cycle (the lcm of the state periods) so periodic classes
+// give their time-averaged share instead of oscillating.
+pub fn limiting_distributions(matrix: &TransitionMatrix) -> Vec<Vec<f64>> {
+    const SQUARINGS: usize = 20;
+    
+    let cycle = (0..matrix.states.len())
+        .map(|start| period_at(matrix, start))
+        .filter(|&p| p > 0)
+        .fold(1, |acc, p| acc / gcd(acc, p) * p);
+    
+    let mut power = matrix.matrix.clone();
+    for _ in 0..SQUARINGS {
+        power = power.dot(&power);
+    }
+    
+    let mut sum = Array2::<f64>::zeros(power.raw_dim());
+    for _ in 0..cycle {
+        sum += &power;
+        power = power.dot(&matrix.matrix);
+    }
+    sum /= cycle as f64;
+    
+    sum.rows().into_iter().map(|row| row.to_vec()).collect()
+}
+
+// Which algorithm produced a steady-state result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SteadyStateMethod {
+    LinearSolve,
+    PowerIteration,
+}
+
+impl fmt::Display for SteadyStateMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SteadyStateMethod::LinearSolve => write!(f, "LinearSolve"),
+            SteadyStateMethod::PowerIteration => write!(f, "PowerIteration"),
+        }
+    }
+}
+
+// Steady-state distribution along with how it was obtained
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SteadyStateResult {
+    pub distribution: Vec<f64>,
+    pub method: SteadyStateMethod,
+    pub converged: bool,
+    // Matrix multiplications used by power iteration; 0 for the linear solve
+    pub iterations: usize,
+}
+
+impl SteadyStateResult {
+    // Power iteration results are approximations even when they converge
+    pub fn is_approximate(&self) -> bool {
+        self.method != SteadyStateMethod::LinearSolve
+    }
+}
+
+// Pick the steady-state method automatically: exact linear solve for irreducible chains,
+// falling back to power iteration when the chain is reducible or the system is singular
+pub fn steady_state_auto(matrix: &TransitionMatrix) -> SteadyStateResult {
+    if is_irreducible(matrix)
+        && let Some(distribution) = steady_state_exact(matrix)
+    {
+        return SteadyStateResult {
+            distribution,
+            method: SteadyStateMethod::LinearSolve,
+            converged: true,
+            iterations: 0,
+        };
+    }
+    
+    let (distribution, converged, iterations) = power_iteration(matrix);
+    SteadyStateResult {
+        distribution,
+        method: SteadyStateMethod::PowerIteration,
+        converged,
+        iterations,
+    }
+}
+
+// Partial derivatives of the steady state with respect to each matrix entry.
+// Row i * n + j holds d(pi) / d(P[i, j]) over the states (columns ordered like matrix.states),
+// where nudging P[i, j] up by e renormalizes row i, i.e. the perturbation is e * (e_j - P_i).
+// Uses the fundamental matrix Z = (I - P + 1 pi)^-1: for a perturbation E with zero row
+// sums, d(pi) = pi E Z, so d(pi) / d(P[i, j]) = pi_i * (Z[j, :] - P_i Z).
+// None when the chain has no unique steady state.
+pub fn steady_state_sensitivity(matrix: &TransitionMatrix) -> Option<Array2<f64>> {
+    let n = matrix.matrix.nrows();
+    let pi = steady_state_exact(matrix)?;
+    let fundamental = fundamental_matrix(matrix, &pi)?;
+    
+    let weighted = matrix.matrix.dot(&fundamental);
+    let mut sensitivity = Array2::<f64>::zeros((n * n, n));
+    for (i, &pi_i) in pi.iter().enumerate() {
+        for j in 0..n {
+            let delta = &fundamental.row(j) - &weighted.row(i);
+            sensitivity.row_mut(i * n + j).assign(&(delta * pi_i));
+        }
+    }
+    Some(sensitivity)
+}
+
+// Condition numbers above this make the linear solves unreliable enough to warn about
+const ILL_CONDITIONED_THRESHOLD: f64 = 1e6;
+
+// 1-norm condition number of I - P + J (J = all ones), the matrix behind the exact
+// steady-state solve: ||A||_1 * ||A^-1||_1. Large values mean the linear-algebra results
+// (steady_state_exact, sensitivity, passage times) may be dominated by rounding error;
+// infinity when A is singular, which happens when the chain has several closed classes.
+pub fn condition_number(matrix: &TransitionMatrix) -> f64 {
+    let n = matrix.matrix.nrows();
+    let mut system = -matrix.matrix.clone() + 1.0;
+    for i in 0..n {
+        system[[i, i]] += 1.0;
+    }
+    
+    let one_norm = |a: &Array2<f64>| a.columns().into_iter()
+        .map(|column| column.iter().map(|v| v.abs()).sum::<f64>())
+        .fold(0.0, f64::max);
+    
+    let mut inverse = Array2::<f64>::zeros((n, n));
+    for k in 0..n {
+        let mut unit = vec![0.0; n];
+        unit[k] = 1.0;
+        match solve_linear_system(system.clone(), unit) {
+            Some(column) => inverse.column_mut(k).assign(&ndarray::Array1::from(column)),
+            None => return f64::INFINITY,
+        }
+    }
+    
+    one_norm(&system) * one_norm(&inverse)
+}
+
+// Fundamental matrix Z = (I - P + 1 pi)^-1, inverted column by column
+fn fundamental_matrix(matrix: &TransitionMatrix, pi: &[f64]) -> Option<Array2<f64>> {
+    let n = matrix.matrix.nrows();
+    let mut system = -matrix.matrix.clone();
+    for i in 0..n {
+        system[[i, i]] += 1.0;
+        for j in 0..n {
+            system[[i, j]] += pi[j];
+        }
+    }
+    
+    let mut fundamental = Array2::<f64>::zeros((n, n));
+    for k in 0..n {
+        let mut unit = vec![0.0; n];
+        unit[k] = 1.0;
+        let column = solve_linear_system(system.clone(), unit)?;
+        fundamental.column_mut(k).assign(&ndarray::Array1::from(column));
+    }
+    Some(fundamental)
+}
+
+// Kemeny constant: expected days to reach a target drawn from the steady state,
+// sum_j pi_j * m(i, j) with m(i, i) = 0, which is the same from every start i.
+// Computed as trace(Z) - 1 from the fundamental matrix; low means well-connected
+// weather. Infinity when the chain has no unique steady state.
+pub fn kemeny_constant(matrix: &TransitionMatrix) -> f64 {
+    steady_state_exact(matrix)
+        .and_then(|pi| fundamental_matrix(matrix, &pi))
+        .map_or(f64::INFINITY, |fundamental| fundamental.diag().sum() - 1.0)
+}
+
+// Solve a * x = b using Gaussian elimination with partial pivoting
+// Returns None when the system is singular
+fn solve_linear_system(mut a: Array2<f64>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    const PIVOT_EPSILON: f64 = 1e-12;
+    let n = b.len();
+    
+    // Forward elimination
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&x, &y| a[[x, col]].abs().total_cmp(&a[[y, col]].abs()))?;
+        if a[[pivot, col]].abs() < PIVOT_EPSILON {
+            return None;
+        }
+        
+        if pivot != col {
+            for k in 0..n {
+                a.swap([pivot, k], [col, k]);
+            }
+            b.swap(pivot, col);
+        }
+        
+        for row in (col + 1)..n {
+            let factor = a[[row, col]] / a[[col, col]];
+            for k in col..n {
+                a[[row, k]] -= factor * a[[col, k]];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    
+    // Back substitution
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let mut sum = b[row];
+        for k in (row + 1)..n {
+            sum -= a[[row, k]] * x[k];
+        }
+        x[row] = sum / a[[row, row]];
+    }
+    
+    Some(x)
+}
+
+// Expected number of steps to first reach the target state from every state
+// The entry for the target itself is its mean recurrence time
+// States that may never reach the target get infinity
+fn first_passage_times_to(matrix: &TransitionMatrix, target: usize) -> Vec<f64> {
+    let n = matrix.matrix.nrows();
+    
+    // Find states that can reach the target at all (reverse reachability)
+    let mut can_reach = vec![false; n];
+    can_reach[target] = true;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 0..n {
+            if !can_reach[i] && (0..n).any(|k| can_reach[k] && matrix.matrix[[i, k]] > 0.0) {
+                can_reach[i] = true;
+                changed = true;
+            }
+        }
+    }
+    
+    // Keep only states that reach the target with certainty: any positive
+    // transition into a state that can't reach it makes the expectation infinite
+    let mut certain: Vec<bool> = (0..n).map(|i| i != target && can_reach[i]).collect();
+    changed = true;
+    while changed {
+        changed = false;
+        for i in 0..n {
+            if certain[i]
+                && (0..n).any(|k| k != target && !certain[k] && matrix.matrix[[i, k]] > 0.0)
+            {
+                certain[i] = false;
+                changed = true;
+            }
+        }
+    }
+    
+    // Solve m_i = 1 + sum_k P[i,k] m_k over the certain states (m_target = 0)
+    let indices: Vec<usize> = (0..n).filter(|&i| certain[i]).collect();
+    let size = indices.len();
+    let mut system = Array2::<f64>::zeros((size, size));
+    for (r, &i) in indices.iter().enumerate() {
+        for (c, &k) in indices.iter().enumerate() {
+            system[[r, c]] = if r == c { 1.0 } else { 0.0 } - matrix.matrix[[i, k]];
+        }
+    }
+    let solution = solve_linear_system(system, vec![1.0; size])
+        .unwrap_or_else(|| vec![f64::INFINITY; size]);
+    
+    let mut times = vec![f64::INFINITY; n];
+    for (r, &i) in indices.iter().enumerate() {
+        times[i] = solution[r];
+    }
+    
+    // Mean recurrence time: one step out, then first passage back
+    let mut recurrence = 1.0;
+    for (k, &time) in times.iter().enumerate() {
+        let p = matrix.matrix[[target, k]];
+        if k != target && p > 0.0 {
+            recurrence += p * time;
+        }
+    }
+    times[target] = recurrence;
+    
+    times
+}
+
+// Mean first passage time: expected days to first reach `to` starting from `from`
+// Returns the mean recurrence time when from == to, and infinity if unreachable
+pub fn mean_first_passage_time(matrix: &TransitionMatrix, from: StateType, to: StateType) -> Result<f64, String> {
+    let from_idx = matrix.require_state(from)?;
+    let to_idx = matrix.require_state(to)?;
+    Ok(first_passage_times_to(matrix, to_idx)[from_idx])
+}
+
+// Expected timestamp of the first day `target` occurs, e.g. "first rain around June 12":
+// start_timestamp plus the mean first passage time in days, rounded to the nearest
+// second. Starting in the target returns start_timestamp itself. Errors when the target
+// is not reached with certainty, since the expectation is then infinite.
+pub fn expected_first_occurrence_date(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    target: StateType,
+    start_timestamp: i64,
+) -> Result<i64, String> {
+    if initial_state == target {
+        return Ok(start_timestamp);
+    }
+    
+    let days = mean_first_passage_time(matrix, initial_state, target)?;
+    if !days.is_finite() {
+        return Err(format!("{} is not reached with certainty from {}", target, initial_state));
+    }
+    Ok(start_timestamp + (days * 86400.0).round() as i64)
+}
+
+// Commute time C(a,b) = m(a,b) + m(b,a): expected round-trip days between two states
+// Symmetric by construction; zero for a state with itself and infinity if either direction is unreachable
+pub fn commute_time(matrix: &TransitionMatrix, a: StateType, b: StateType) -> Result<f64, String> {
+    if a == b {
+        matrix.require_state(a)?;
+        return Ok(0.0);
+    }
+    Ok(mean_first_passage_time(matrix, a, b)? + mean_first_passage_time(matrix, b, a)?)
+}
+
+// Self-information in bits of one observed transition: -log2(P[previous, observed]).
+// Spikes on unusual days; an impossible transition gives infinity, which flags either a
+// gap in the model or an error in the data.
+pub fn surprise(matrix: &TransitionMatrix, previous: StateType, observed: StateType) -> Result<f64, String> {
+    let i = matrix.require_state(previous)?;
+    let j = matrix.require_state(observed)?;
+    Ok(-matrix.matrix[[i, j]].log2())
+}
+
+// Joint probability of the observed history under the model: the product of its
+// consecutive transition probabilities (1.0 for a single-state history). This underflows
+// quickly, so prefer log_likelihood beyond ~30 transitions; the raw value is mainly
+// useful for short demos and teaching. Errors if the history visits a state the matrix
+// doesn't list.
+pub fn history_probability(matrix: &TransitionMatrix, data: &HistoricalData) -> Result<f64, String> {
+    data.state_pairs()
+        .map(|(current, next)| {
+            let from = matrix.require_state(current.state)?;
+            let to = matrix.require_state(next.state)?;
+            Ok(matrix.matrix[[from, to]])
+        })
+        .product()
+}
+
+// Natural-log likelihood of the observed history; negative infinity if any
+// observed transition has zero probability
+pub fn log_likelihood(matrix: &TransitionMatrix, data: &HistoricalData) -> Result<f64, String> {
+    data.state_pairs()
+        .map(|(current, next)| {
+            let from = matrix.require_state(current.state)?;
+            let to = matrix.require_state(next.state)?;
+            Ok(matrix.matrix[[from, to]].ln())
+        })
+        .sum()
+}
+
+// Find the consecutive pair in the history whose transition was least probable under the model
+// Returns the index of the pair's first day and the transition probability; None when
+// the history has fewer than two days or visits a state the matrix doesn't list
+pub fn least_likely_transition(matrix: &TransitionMatrix, data: &HistoricalData) -> Option<(usize, f64)> {
+    let mut least: Option<(usize, f64)> = None;
+    
+    for (idx, (current, next)) in data.state_pairs().enumerate() {
+        let from = matrix.state_index(current.state)?;
+        let to = matrix.state_index(next.state)?;
+        let probability = matrix.matrix[[from, to]];
+        
+        // Keep the earliest pair on ties
+        if least.is_none_or(|(_, p)| probability < p) {
+            least = Some((idx, probability));
+        }
+    }
+    
+    least
+}
+
+// What best_initial_state optimizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Objective {
+    // Chance of seeing the target at least once in days 1..=days
+    MaximizeHittingProbability,
+    // Expected number of target days among days 1..=days
+    MaximizeExpectedDays,
+}
+
+// Starting state that maximizes the objective for the target state
+// Only future days count, otherwise starting in the target would trivially win
+pub fn best_initial_state(
+    matrix: &TransitionMatrix,
+    target: StateType,
+    days: usize,
+    objective: Objective,
+) -> Result<StateType, String> {
+    Ok(best_initial_state_scored(matrix, target, days, objective)?.0)
+}
+
+// Same as best_initial_state, also returning every candidate's score (ties go to the first state)
+pub fn best_initial_state_scored(
+    matrix: &TransitionMatrix,
+    target: StateType,
+    days: usize,
+    objective: Objective,
+) -> Result<(StateType, Vec<(StateType, f64)>), String> {
+    let target_idx = matrix.require_state(target)?;
+    
+    let scores: Vec<(StateType, f64)> = matrix.states.iter().enumerate().map(|(from, &candidate)| {
+        let score = match objective {
+            Objective::MaximizeHittingProbability if days > 0 => {
+                (0..matrix.states.len())
+                    .map(|k| matrix.matrix[[from, k]] * hit_from_index(matrix, k, target_idx, days - 1))
+                    .sum()
+            }
+            Objective::MaximizeHittingProbability => 0.0,
+            Objective::MaximizeExpectedDays => {
+                forecast_from_index(matrix, from, days + 1)
+                    .iter()
+                    .skip(1)
+                    .map(|dist| dist[target_idx])
+                    .sum()
+            }
+        };
+        (candidate, score)
+    }).collect();
+    
+    let mut best = scores[0];
+    for &(candidate, score) in &scores[1..] {
+        if score > best.1 {
+            best = (candidate, score);
+        }
+    }
+    
+    Ok((best.0, scores))
+}
+
+// Total variation distance between two distributions: half the L1 distance, in [0, 1]
+pub fn total_variation_distance(p: &[f64], q: &[f64]) -> f64 {
+    0.5 * p.iter().zip(q.iter()).map(|(a, b)| (a - b).abs()).sum::<f64>()
+}
+
+// Distance between two matrices: the mean total variation distance of corresponding rows
+pub fn matrix_total_variation(a: &TransitionMatrix, b: &TransitionMatrix) -> f64 {
+    let n = a.matrix.nrows();
+    if n == 0 {
+        return 0.0;
+    }
+    let total: f64 = a.matrix.rows().into_iter()
+        .zip(b.matrix.rows())
+        .map(|(row_a, row_b)| total_variation_distance(&row_a.to_vec(), &row_b.to_vec()))
+        .sum();
+    total / n as f64
+}
+
+// Whether a second-order model forecasts differently enough to be worth it: the mean
+// total variation distance between the first- and second-order exact forecasts over
+// the `days` days after the last state in `initial` (which needs the last two states).
+// Near 0 means the extra order buys nothing.
+pub fn order_disagreement(data: &HistoricalData, initial: &[StateType], days: usize) -> Result<f64, String> {
+    if days == 0 {
+        return Err("Need at least one forecast day".to_string());
+    }
+    let [.., prev, current] = initial else {
+        return Err("Need the last two states to start a second-order forecast".to_string());
+    };
+    
+    let first_order = build_transition_matrix(data);
+    let second_order = SecondOrderModel::train(data);
+    let first = forecast_distribution(&first_order, *current, days + 1)?;
+    let second = second_order.forecast_distribution(*prev, *current, days + 1);
+    
+    // Both matrices use Sunny, Rainy, Cloudy order, so distributions line up
+    let total: f64 = first.iter().zip(second.iter())
+        .skip(1)
+        .map(|(p, q)| total_variation_distance(p, q))
+        .sum();
+    Ok(total / days as f64)
+}
+
+// How much the transition dynamics drift over the history
+// Splits the series into consecutive, non-overlapping windows of `window` observations
+// (window = 0 means two halves), trains a matrix on each, and returns the largest
+// distance between neighbouring windows. Near 0 means a single matrix summarizes the
+// history well; large values mean the dynamics changed. Short windows leave unseen rows
+// uniform, which inflates the score, so prefer windows with many transitions per state.
+pub fn stationarity_score(data: &HistoricalData, window: usize) -> f64 {
+    let window = if window == 0 { data.len() / 2 } else { window };
+    if window < 2 {
+        return 0.0;
+    }
+    
+    let matrices: Vec<TransitionMatrix> = data.states.chunks_exact(window)
+        .map(|chunk| {
+            let mut segment = HistoricalData::new(data.location.clone());
+            segment.states = chunk.to_vec();
+            build_transition_matrix(&segment)
+        })
+        .collect();
+    
+    matrices.windows(2)
+        .map(|pair| matrix_total_variation(&pair[0], &pair[1]))
+        .fold(0.0, f64::max)
+}
+
+// Index where the transition dynamics shift the most: tries every split that leaves at
+// least `min_segment` observations on each side, trains a matrix on each side, and
+// returns the split with the largest matrix_total_variation between them if it exceeds
+// 0.25. Rows a segment never visits stay uniform, so keep segments long.
+pub fn detect_regime_change(data: &HistoricalData, min_segment: usize) -> Option<usize> {
+    const REGIME_CHANGE_THRESHOLD: f64 = 0.25;
+    
+    let min_segment = min_segment.max(2);
+    if data.len() < 2 * min_segment {
+        return None;
+    }
+    
+    let segment_matrix = |states: &[WeatherState]| {
+        let mut segment = HistoricalData::new(data.location.clone());
+        segment.states = states.to_vec();
+        build_transition_matrix(&segment)
+    };
+    
+    let mut best: Option<(usize, f64)> = None;
+    for split in min_segment..=(data.len() - min_segment) {
+        let distance = matrix_total_variation(
+            &segment_matrix(&data.states[..split]),
+            &segment_matrix(&data.states[split..]),
+        );
+        if best.is_none_or(|(_, best_distance)| distance > best_distance) {
+            best = Some((split, distance));
+        }
+    }
+    
+    best.filter(|&(_, distance)| distance > REGIME_CHANGE_THRESHOLD)
+        .map(|(split, _)| split)
+}
+
+// Pairs of states whose outgoing distributions are within `tol` total variation distance
+// Such states are effectively indistinguishable to the model
+pub fn similar_state_pairs(matrix: &TransitionMatrix, tol: f64) -> Vec<(StateType, StateType, f64)> {
+    let n = matrix.states.len();
+    let mut pairs = Vec::new();
+    
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let distance = total_variation_distance(
+                &matrix.matrix.row(i).to_vec(),
+                &matrix.matrix.row(j).to_vec(),
+            );
+            if distance < tol {
+                pairs.push((matrix.states[i], matrix.states[j], distance));
+            }
+        }
+    }
+    
+    pairs
+}
+
+// Inverse of the standard normal CDF (Acklam's rational approximation, ~1e-9 relative error)
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02,
+        1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02,
+        6.680131188771972e+01, -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00,
+        -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const P_LOW: f64 = 0.02425;
+    
+    if p < P_LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -normal_quantile(1.0 - p)
+    }
+}
+
+// Transitions needed from a state so each estimated probability has a confidence interval
+// of +/- target_margin, using the normal-approximation binomial formula n = z^2 p(1-p) / E^2
+// with the worst case p = 0.5. Inputs outside (0, 1) return 0.
+pub fn recommended_sample_size(target_margin: f64, confidence: f64) -> usize {
+    if !(target_margin > 0.0 && target_margin < 1.0 && confidence > 0.0 && confidence < 1.0) {
+        return 0;
+    }
+    
+    let z = normal_quantile(1.0 - (1.0 - confidence) / 2.0);
+    (z * z * 0.25 / (target_margin * target_margin)).ceil() as usize
+}
+
+// Days of autocorrelated history needed for the same precision as
+// recommended_sample_size independent samples: the raw count is inflated by
+// N / effective_sample_size(data), the persistence observed in this history
+pub fn recommended_sample_size_autocorrelated(target_margin: f64, confidence: f64, data: &HistoricalData) -> usize {
+    let base = recommended_sample_size(target_margin, confidence);
+    let effective = effective_sample_size(data);
+    if data.is_empty() || effective <= 0.0 {
+        return base;
+    }
+    (base as f64 * data.len() as f64 / effective).ceil() as usize
+}
+
+// Expected total payoff over the forecast horizon, weighting each day's exact
+// distribution by per-state payoffs (ordered like matrix.states); day 0 is today
+pub fn expected_payoff(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    payoffs: &[f64],
+    days: usize,
+) -> Result<f64, String> {
+    if payoffs.len() != matrix.states.len() {
+        return Err(format!(
+            "Expected {} payoffs (one per state), got {}",
+            matrix.states.len(), payoffs.len()
+        ));
+    }
+    
+    Ok(forecast_distribution(matrix, initial_state, days)?
+        .iter()
+        .map(|dist| dist.iter().zip(payoffs.iter()).map(|(p, v)| p * v).sum::<f64>())
+        .sum())
+}
+
+// The day in 1..=days most likely to have the `target` weather, with that probability,
+// from the exact per-day forecast ("when should I plan the picnic"). Ties go to the
+// earlier day.
+pub fn best_day_for(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    target: StateType,
+    days: usize,
+) -> Result<(usize, f64), String> {
+    if days == 0 {
+        return Err("Need at least one day to choose from".to_string());
+    }
+    let target_idx = matrix.require_state(target)?;
+    
+    let mut best = (1, f64::NEG_INFINITY);
+    for (day, dist) in forecast_distribution(matrix, initial_state, days + 1)?.iter().enumerate().skip(1) {
+        if dist[target_idx] > best.1 {
+            best = (day, dist[target_idx]);
+        }
+    }
+    Ok(best)
+}
+
+// Expected number of weather changes across a `days`-day forecast (day 0 is today, so
+// there are days - 1 transitions). Each day contributes the probability mass that
+// leaves its state tomorrow: sum_i p_d(i) * (1 - P[i, i])
+pub fn expected_transitions(matrix: &TransitionMatrix, initial_state: StateType, days: usize) -> Result<f64, String> {
+    let distributions = forecast_distribution(matrix, initial_state, days)?;
+    Ok(distributions.iter()
+        .take(days.saturating_sub(1))
+        .map(|dist| dist.iter().enumerate()
+            .map(|(i, p)| p * (1.0 - matrix.matrix[[i, i]]))
+            .sum::<f64>())
+        .sum())
+}
+
+// Walk-forward validation of one-step forecasts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestResult {
+    // Number of days that were predicted
+    pub predictions: usize,
+    pub accuracy: f64,
+    // Share of days with each actual state that were predicted correctly; 0 if never seen
+    pub per_state_accuracy: StateProbabilities,
+    pub mean_log_loss: f64,
+}
+
+// Backtest the model on its own history: for every day from index `warmup` on, train on
+// all earlier days, predict the most likely next state from the day before (ties go to
+// the first state in matrix order), and score it against what happened. Log-loss uses
+// the natural log with probabilities floored at 1e-15, so an impossible outcome costs
+// about 34.5 instead of infinity. A warmup below 1 is treated as 1.
+pub fn backtest(data: &HistoricalData, warmup: usize) -> BacktestResult {
+    const MIN_PROBABILITY: f64 = 1e-15;
+    
+    let states = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+    let mut hits = [0usize; 3];
+    let mut seen = [0usize; 3];
+    let mut log_loss = 0.0;
+    let mut predictions = 0;
+    
+    for i in warmup.max(1)..data.len() {
+        let prefix = HistoricalData { states: data.states[..i].to_vec(), ..data.clone() };
+        let matrix = build_transition_matrix(&prefix);
+        let probabilities = next_state_distribution(&matrix, data.states[i - 1].state)
+            .expect("trained matrices list every weather state");
+        let predicted = probabilities.iter()
+            .enumerate()
+            .fold(0, |best, (j, &p)| if p > probabilities[best] { j } else { best });
+        
+        let actual = data.states[i].state;
+        let actual_idx = states.iter().position(|&s| s == actual).unwrap();
+        seen[actual_idx] += 1;
+        if matrix.states[predicted] == actual {
+            hits[actual_idx] += 1;
+        }
+        let p = probabilities[matrix.state_index(actual).expect("trained matrices list every weather state")];
+        log_loss -= p.max(MIN_PROBABILITY).ln();
+        predictions += 1;
+    }
+    
+    let per_state: Vec<f64> = hits.iter().zip(seen.iter())
+        .map(|(&h, &n)| if n > 0 { h as f64 / n as f64 } else { 0.0 })
+        .collect();
+    let total_hits: usize = hits.iter().sum();
+    let (accuracy, mean_log_loss) = if predictions > 0 {
+        (total_hits as f64 / predictions as f64, log_loss / predictions as f64)
+    } else {
+        (0.0, 0.0)
+    };
+    
+    BacktestResult {
+        predictions,
+        accuracy,
+        per_state_accuracy: StateProbabilities::from_states(&states, &per_state),
+        mean_log_loss,
+    }
+}
+
+// Multi-category Brier score of a forecast against what actually happened: the mean over
+// days of sum_k (p_k - o_k)^2, where o is the one-hot actual outcome. 0 is perfect, 2 is
+// worst. Both slices include day 0 like forecast_distribution; that seed day is known, not
+// forecast, so it is skipped. Each distribution is ordered like `states`, normally the
+// matrix.states of the matrix that produced the forecast.
+pub fn brier_score(forecast: &[Vec<f64>], states: &[StateType], actual: &[StateType]) -> Result<f64, String> {
+    brier_score_weighted(forecast, states, actual, |a, b| if a == b { 0.0 } else { 1.0 })
+}
+
+// Brier score where each wrong category's squared error is scaled by its distance from
+// the actual outcome, so being off by two categories costs more than being off by one.
+// Pass state_distance for the ordinal scale, or any other metric to override it; a metric
+// that puts every pair of states at distance 1 reproduces brier_score.
+pub fn brier_score_weighted(
+    forecast: &[Vec<f64>],
+    states: &[StateType],
+    actual: &[StateType],
+    distance: impl Fn(StateType, StateType) -> f64,
+) -> Result<f64, String> {
+    if forecast.len() != actual.len() {
+        return Err(format!(
+            "Forecast covers {} days but {} actual outcomes were given",
+            forecast.len(), actual.len()
+        ));
+    }
+    if forecast.len() < 2 {
+        return Err("Need at least one forecast day after the seed day".to_string());
+    }
+    if let Some(dist) = forecast.iter().find(|dist| dist.len() != states.len()) {
+        return Err(format!("Forecast day has {} probabilities for {} states", dist.len(), states.len()));
+    }
+    if let Some(outcome) = actual.iter().find(|outcome| !states.contains(outcome)) {
+        return Err(format!("Actual outcome {} is not one of the forecast's states", outcome));
+    }
+    
+    let total: f64 = forecast.iter().zip(actual.iter()).skip(1)
+        .map(|(dist, &outcome)| dist.iter().zip(states.iter())
+            .map(|(p, &state)| if state == outcome {
+                (p - 1.0).powi(2)
+            } else {
+                p.powi(2) * distance(state, outcome)
+            })
+            .sum::<f64>())
+        .sum();
+    Ok(total / (forecast.len() - 1) as f64)
+}
+
+// Probability that `state` holds for the next `days` consecutive days without a break,
+// P[state, state]^days. This is the survival function of the geometric streak length,
+// e.g. the odds of a full dry week starting from a sunny day.
+pub fn survival_probability(matrix: &TransitionMatrix, state: StateType, days: usize) -> Result<f64, String> {
+    let idx = matrix.require_state(state)?;
+    // powf rather than powi, which would wrap `days` past i32::MAX into a negative exponent
+    Ok(matrix.matrix[[idx, idx]].powf(days as f64))
+}
+
+// Probability of seeing the target state at least once within the next `days` days
+// Computed exactly by making the target absorbing and propagating the initial state;
+// starting in the target counts as a hit, so days = 0 gives 1.0 or 0.0
+pub fn hitting_probability(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    target: StateType,
+    days: usize,
+) -> Result<f64, String> {
+    let start = matrix.require_state(initial_state)?;
+    let target_idx = matrix.require_state(target)?;
+    Ok(hit_from_index(matrix, start, target_idx, days))
+}
+
+// Helper function for hitting_probability between known state indices
+fn hit_from_index(matrix: &TransitionMatrix, start: usize, target_idx: usize, days: usize) -> f64 {
+    // Absorbing augmentation: once the target is reached, stay there
+    let mut absorbing = matrix.matrix.clone();
+    absorbing.row_mut(target_idx).fill(0.0);
+    absorbing[[target_idx, target_idx]] = 1.0;
+    
+    let mut current = ndarray::Array1::<f64>::zeros(matrix.matrix.nrows());
+    current[start] = 1.0;
+    for _ in 0..days {
+        current = current.dot(&absorbing);
+    }
+    
+    current[target_idx].clamp(0.0, 1.0)
+}
+
+// Entropy rate of the chain in bits per day: sum_i pi_i * H(P[i, :])
+pub fn entropy_rate(matrix: &TransitionMatrix) -> f64 {
+    let steady_state = steady_state_auto(matrix).distribution;
+    steady_state.iter()
+        .zip(matrix.matrix.rows())
+        .map(|(&pi, row)| pi * distribution_entropy(&row.to_vec()))
+        .sum()
+}
+
+// How much knowing today reduces uncertainty about tomorrow, in bits:
+// H(steady state) - entropy rate. Near zero means today says little about tomorrow.
+pub fn information_gain(matrix: &TransitionMatrix) -> f64 {
+    let steady_state = steady_state_auto(matrix).distribution;
+    (distribution_entropy(&steady_state) - entropy_rate(matrix)).max(0.0)
+}
+
+// KL divergence of the steady state from uniform, in bits: sum_i pi_i * log2(pi_i * n).
+// Zero for a perfectly balanced climate, up to log2(n) when one state always holds.
+// Unlike entropy_rate this describes the long-run marginals, not the transitions.
+pub fn steady_state_nonuniformity(matrix: &TransitionMatrix) -> f64 {
+    let steady_state = steady_state_auto(matrix).distribution;
+    let n = steady_state.len() as f64;
+    steady_state.iter()
+        .filter(|&&pi| pi > 0.0)
+        .map(|&pi| pi * (pi * n).log2())
+        .sum::<f64>()
+        .max(0.0)
+}
+
+// Ordinal encoding of states from best to worst weather: Sunny=0, Cloudy=1, Rainy=2
+fn ordinal_value(state: StateType) -> f64 {
+    match state {
+        StateType::Sunny => 0.0,
+        StateType::Cloudy => 1.0,
+        StateType::Rainy => 2.0,
+    }
+}
+
+// Distance between two states on the ordinal scale (Sunny=0, Cloudy=1, Rainy=2), so
+// Sunny and Rainy are two steps apart while Cloudy is one step from either. Other
+// conditions such as fog or snow are folded into these three states by the classifiers.
+pub fn state_distance(a: StateType, b: StateType) -> f64 {
+    (ordinal_value(a) - ordinal_value(b)).abs()
+}
+
+// Probability that tomorrow is strictly better than today on the ordinal scale
+// (Rainy -> Cloudy -> Sunny improves); always 0 from Sunny
+pub fn improvement_probability(matrix: &TransitionMatrix, current: StateType) -> Result<f64, String> {
+    trend_mass(matrix, current, |next| ordinal_value(next) < ordinal_value(current))
+}
+
+// Probability that tomorrow is strictly worse than today; always 0 from Rainy
+pub fn deterioration_probability(matrix: &TransitionMatrix, current: StateType) -> Result<f64, String> {
+    trend_mass(matrix, current, |next| ordinal_value(next) > ordinal_value(current))
+}
+
+// Helper function summing tomorrow's probabilities over the states matching a condition
+fn trend_mass(matrix: &TransitionMatrix, current: StateType, matches: impl Fn(StateType) -> bool) -> Result<f64, String> {
+    Ok(next_state_distribution(matrix, current)?
+        .iter()
+        .zip(matrix.states.iter())
+        .filter(|&(_, &next)| matches(next))
+        .map(|(p, _)| p)
+        .sum())
+}
+
+// Pearson correlation coefficient; None when either series has zero variance
+fn pearson_correlation(x: &[f64], y: &[f64]) -> Option<f64> {
+    let n = x.len().min(y.len());
+    if n == 0 {
+        return None;
+    }
+    let mean_x = x[..n].iter().sum::<f64>() / n as f64;
+    let mean_y = y[..n].iter().sum::<f64>() / n as f64;
+    
+    let mut covariance = 0.0;
+    let mut variance_x = 0.0;
+    let mut variance_y = 0.0;
+    for (a, b) in x[..n].iter().zip(y[..n].iter()) {
+        covariance += (a - mean_x) * (b - mean_y);
+        variance_x += (a - mean_x).powi(2);
+        variance_y += (b - mean_y).powi(2);
+    }
+    
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return None;
+    }
+    Some((covariance / (variance_x * variance_y).sqrt()).clamp(-1.0, 1.0))
+}
+
+// Correlation in [-1, 1] between two locations' weather, aligned by calendar day
+// States are encoded ordinally (Sunny=0, Cloudy=1, Rainy=2) before correlating
+pub fn state_correlation(a: &HistoricalData, b: &HistoricalData) -> Result<f64, String> {
+    const MIN_OVERLAP: usize = 3;
+    
+    let mut x = Vec::new();
+    let mut y = Vec::new();
+    for weather_a in a.iter() {
+        let day = weather_a.timestamp.div_euclid(86400);
+        if let Some(weather_b) = b.iter().find(|w| w.timestamp.div_euclid(86400) == day) {
+            x.push(ordinal_value(weather_a.state));
+            y.push(ordinal_value(weather_b.state));
+        }
+    }
+    
+    if x.len() < MIN_OVERLAP {
+        return Err(format!(
+            "Insufficient overlap: {} shared days, need at least {}",
+            x.len(), MIN_OVERLAP
+        ));
+    }
+    
+    pearson_correlation(&x, &y)
+        .ok_or_else(|| "Correlation undefined: one location's weather never changes over the overlap".to_string())
+}
+
+// Correlation between the ordinally encoded state series and itself shifted by k days,
+// for k = 1..=max_lag. Lags that leave fewer than two overlapping pairs are dropped, so
+// short series return fewer entries; a lag whose window never changes state reports 0.0
+pub fn autocorrelation(data: &HistoricalData, max_lag: usize) -> Vec<f64> {
+    let series: Vec<f64> = data.iter().map(|w| ordinal_value(w.state)).collect();
+    let max_lag = max_lag.min(series.len().saturating_sub(2));
+    
+    (1..=max_lag)
+        .map(|k| pearson_correlation(&series[..series.len() - k], &series[k..]).unwrap_or(0.0))
+        .collect()
+}
+
+// Number of independent samples the history is worth, N * (1 - rho) / (1 + rho) with rho
+// the lag-1 autocorrelation of the ordinal state series. This is the AR(1) approximation:
+// it ignores longer-range correlation and treats the state ordering as numeric, so read it
+// as a rough discount for persistence rather than an exact count. Between 1 and N for
+// non-empty data; series too short to estimate rho are taken at face value. Negative
+// rho (alternating weather) is treated as 0, so the history is never worth more than N
+// independent days.
+pub fn effective_sample_size(data: &HistoricalData) -> f64 {
+    let n = data.len() as f64;
+    if data.is_empty() {
+        return 0.0;
+    }
+    let rho = autocorrelation(data, 1).first().copied().unwrap_or(0.0)
+        .clamp(0.0, 1.0 - f64::EPSILON);
+    (n * (1.0 - rho) / (1.0 + rho)).clamp(1.0, n)
+}
+
+// First day on which the forecast from `initial_state` is within `tol` total variation
+// distance of the steady state; None if that doesn't happen within 10,000 days or the
+// matrix doesn't list the initial state
+pub fn mixing_time(matrix: &TransitionMatrix, initial_state: StateType, tol: f64) -> Option<usize> {
+    const MAX_MIXING_DAYS: usize = 10_000;
+    
+    let start = matrix.state_index(initial_state)?;
+    let steady_state = steady_state_auto(matrix).distribution;
+    let mut current = ndarray::Array1::<f64>::zeros(matrix.matrix.nrows());
+    current[start] = 1.0;
+    
+    for day in 0..=MAX_MIXING_DAYS {
+        if total_variation_distance(&current.to_vec(), &steady_state) <= tol {
+            return Some(day);
+        }
+        current = current.dot(&matrix.matrix);
+    }
+    None
+}
+
+// Conservative forecast horizon: the day by which every pure starting state has mixed
+// to within `tol` of the steady state. Periodic chains never mix and return an error.
+pub fn mixing_time_worst_case(matrix: &TransitionMatrix, tol: f64) -> Result<usize, String> {
+    let chain_period = period(matrix);
+    if chain_period > 1 {
+        return Err(format!("Chain is periodic (period {}) and never mixes", chain_period));
+    }
+    
+    let mut worst = 0;
+    for &state in &matrix.states {
+        let day = mixing_time(matrix, state, tol)
+            .ok_or_else(|| format!("Chain does not mix from {} within tolerance {}", state, tol))?;
+        worst = worst.max(day);
+    }
+    Ok(worst)
+}
+
+// Long-run fraction of time spent in any of the given states (e.g. "not rainy")
+// Repeated states are only counted once
+pub fn steady_state_subset(matrix: &TransitionMatrix, states: &[StateType]) -> f64 {
+    let steady_state = steady_state_auto(matrix).distribution;
+    matrix.states.iter()
+        .zip(steady_state.iter())
+        .filter(|(state, _)| states.contains(state))
+        .map(|(_, &pi)| pi)
+        .sum()
+}
+
+// Persistence index in [0, 1]: sum_i pi_i * P[i, i], the long-run chance that
+// tomorrow repeats today. High means sticky weather, low means it flips around.
+pub fn persistence_index(matrix: &TransitionMatrix) -> f64 {
+    let steady_state = steady_state_auto(matrix).distribution;
+    steady_state.iter()
+        .enumerate()
+        .map(|(i, &pi)| pi * matrix.matrix[[i, i]])
+        .sum::<f64>()
+        .clamp(0.0, 1.0)
+}
+
+// Largest detailed-balance violation below which a chain counts as reversible
+const REVERSIBILITY_TOLERANCE: f64 = 1e-9;
+
+// How far the chain is from reversible: max over state pairs of the net probability
+// flow |pi_i * P[i, j] - pi_j * P[j, i]|. Zero for a reversible chain; a nonzero value
+// means some change runs one way more than the other (e.g. clearing up faster than
+// clouding over).
+pub fn reversibility_defect(matrix: &TransitionMatrix) -> f64 {
+    let steady_state = steady_state_auto(matrix).distribution;
+    let n = matrix.matrix.nrows();
+    let mut defect: f64 = 0.0;
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let flow = steady_state[i] * matrix.matrix[[i, j]] - steady_state[j] * matrix.matrix[[j, i]];
+            defect = defect.max(flow.abs());
+        }
+    }
+    defect
+}
+
+// Whether the chain satisfies detailed balance within REVERSIBILITY_TOLERANCE
+pub fn is_reversible(matrix: &TransitionMatrix) -> bool {
+    reversibility_defect(matrix) < REVERSIBILITY_TOLERANCE
+}
+
+// Directional bias for every unordered pair of states: (a, b, P[a, b] - P[b, a]), with
+// a before b in matrix order. Positive means a -> b is the likelier direction. Sorted by
+// magnitude, largest first, so the strongest asymmetries lead the report.
+pub fn transition_asymmetry(matrix: &TransitionMatrix) -> Vec<(StateType, StateType, f64)> {
+    let n = matrix.states.len();
+    let mut pairs = Vec::with_capacity(n * n.saturating_sub(1) / 2);
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let difference = matrix.matrix[[i, j]] - matrix.matrix[[j, i]];
+            pairs.push((matrix.states[i], matrix.states[j], difference));
+        }
+    }
+    pairs.sort_by(|a, b| b.2.abs().total_cmp(&a.2.abs()));
+    pairs
+}
+
+// Time-reversed chain: P*[i, j] = pi_j * P[j, i] / pi_i, answering "given today, what was
+// yesterday". Errors if any state has zero steady-state probability.
+pub fn reverse_matrix(matrix: &TransitionMatrix) -> Result<TransitionMatrix, String> {
+    const MIN_PROBABILITY: f64 = 1e-12;
+    
+    let steady_state = steady_state_auto(matrix).distribution;
+    if let Some(i) = steady_state.iter().position(|&pi| pi < MIN_PROBABILITY) {
+        return Err(format!(
+            "State {} has zero steady-state probability; the reversed chain is undefined",
+            matrix.states[i]
+        ));
+    }
+    
+    let n = matrix.matrix.nrows();
+    let mut reversed = TransitionMatrix {
+        matrix: Array2::<f64>::zeros((n, n)),
+        states: matrix.states.clone(),
+        counts: None,
+    };
+    for i in 0..n {
+        for j in 0..n {
+            reversed.matrix[[i, j]] = steady_state[j] * matrix.matrix[[j, i]] / steady_state[i];
+        }
+    }
+    reversed.normalize_rows();
+    
+    Ok(reversed)
+}
+
+// WASM Bindings and JavaScript Interface
+
+#[wasm_bindgen]
+pub fn init_markov_engine() -> Result<(), JsValue> {
+    // Set up panic hook for better error messages in browser console
+    #[cfg(feature = "console_error_panic_hook")]
+    console_error_panic_hook::set_once();
+    
+    // Initialize random number generator for WASM environment
+    // getrandom is already configured with "js" feature in Cargo.toml
+    
+    // Clear any existing state
+    clear_model();
+    
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn clear_model() {
+    // Drop the trained matrix, its history, simulation results and the processing cache,
+    // and restore the Cloudy fallback so a reset engine classifies like a fresh one
+    *TRANSITION_MATRIX.lock().unwrap() = None;
+    *SIMULATION_RESULTS.lock().unwrap() = None;
+    *LAST_PROCESSED.lock().unwrap() = None;
+    *STORED_HISTORY.lock().unwrap() = None;
+    *DEFAULT_STATE.lock().unwrap() = StateType::Cloudy;
+}
+
+// Resubmitting the same JSON returns the cached MatrixData without re-parsing
+// or retraining, unless `force` is set
+#[wasm_bindgen]
+pub fn process_weather_data(json_str: &str, force: bool) -> Result<JsValue, JsValue> {
+    process_weather_data_with(json_str, force, &WasmSerializer)
+}
+
+// process_weather_data with a pluggable output format
+pub fn process_weather_data_with<S: ResultSerializer>(
+    json_str: &str,
+    force: bool,
+    serializer: &S,
+) -> Result<S::Output, S::Error> {
+    let default_state = *DEFAULT_STATE.lock().unwrap();
+    let input_hash = processing_hash(json_str, default_state);
+    if !force
+        && let Some((hash, matrix_data)) = LAST_PROCESSED.lock().unwrap().as_ref()
+        && *hash == input_hash
+    {
+        return serializer.serialize_matrix(matrix_data);
+    }
+    
+    // Call parse_weather_data to convert JSON to HistoricalData, honoring set_default_state
+    let options = ParseOptions {
+        default_state: Some(default_state),
+        ..Default::default()
+    };
+    let historical_data = parse_weather_data_with_options(json_str, &options)
+        .map_err(|e| format!("Failed to parse weather data: {}", e))?;
+    
+    let matrix_data = train_and_store(&historical_data)?;
+    *LAST_PROCESSED.lock().unwrap() = Some((input_hash, matrix_data.clone()));
+    serializer.serialize_matrix(&matrix_data)
+}
+
+// Helper function to key the processing cache on the raw input and the default state,
+// since changing set_default_state changes what the same JSON trains
+fn processing_hash(json_str: &str, default_state: StateType) -> u64 {
+    use std::hash::{Hash, Hasher};
+    
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    json_str.hash(&mut hasher);
+    default_state.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[wasm_bindgen]
+pub fn process_weather_data_with_classifier(
+    json_str: &str,
+    classify_fn: &js_sys::Function,
+) -> Result<JsValue, JsValue> {
+    // Ask JavaScript for the state name of every condition string
+    let classify = |condition: &str| -> Result<StateType, String> {
+        let result = classify_fn.call1(&JsValue::NULL, &JsValue::from_str(condition))
+            .map_err(|e| format!("Classifier threw for condition '{}': {:?}", condition, e))?;
+        let name = result.as_string()
+            .ok_or_else(|| format!("Classifier must return a state name string for condition '{}'", condition))?;
+        name.parse::<StateType>().map_err(|_| format!(
+            "Classifier returned unrecognized state '{}' for condition '{}'. Must be 'Sunny', 'Rainy', or 'Cloudy'",
+            name, condition
+        ))
+    };
+    
+    let historical_data = parse_weather_data_with_classifier(json_str, &ParseOptions::default(), classify)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse weather data: {}", e)))?;
+    
+    let matrix_data = train_and_store(&historical_data)?;
+    WasmSerializer.serialize_matrix(&matrix_data)
+}
+
+// Run the whole pipeline on a WeatherAPI response without touching the engine's stored
+// state: parse with `options`, train, forecast `days` days from the last observation with
+// a seeded generator, and gather statistics for that forecast
+pub fn generate_report(
+    json_str: &str,
+    days: usize,
+    seed: u64,
+    options: &ParseOptions,
+) -> Result<WeatherReport, EngineError> {
+    validate_simulation_days(days).map_err(EngineError::InvalidInput)?;
+    
+    let historical_data = parse_weather_data_with_options(json_str, options)?;
+    let (matrix, warnings) = build_transition_matrix_checked(&historical_data);
+    if !matrix.is_stochastic() {
+        return Err(EngineError::InvalidMatrix("Generated transition matrix is not stochastic".to_string()));
+    }
+    
+    let initial_state = historical_data.states.last()
+        .map(|w| w.state)
+        .ok_or_else(|| EngineError::InvalidInput("No observations to forecast from".to_string()))?;
+    let forecast = try_simulate_weather_with_rng(&matrix, initial_state, days, &mut SeededRandom::new(seed))?;
+    
+    let mut matrix_data = MatrixData::from(&matrix);
+    matrix_data.warnings = warnings.iter().map(|w| w.to_string()).collect();
+    matrix_data.coverage = Some(historical_data.coverage(86400));
+    
+    Ok(WeatherReport {
+        location: historical_data.location.clone(),
+        num_observations: historical_data.len(),
+        matrix_data,
+        statistics: compute_statistics(&matrix, Some(&forecast)),
+        forecast: simulation_days(&forecast, 0),
+    })
+}
+
+#[wasm_bindgen]
+pub fn generate_weather_report(json_str: &str, days: usize, seed: u32) -> Result<JsValue, JsValue> {
+    // Honor set_default_state like process_weather_data does
+    let options = ParseOptions {
+        default_state: Some(*DEFAULT_STATE.lock().unwrap()),
+        ..Default::default()
+    };
+    let report = generate_report(json_str, days, seed as u64, &options)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    
+    serde_wasm_bindgen::to_value(&report)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize report: {}", e)))
+}
+
+// Run one seeded simulation and summarize that same run, without touching the
+// stored simulation results
+pub fn simulate_and_summarize(
+    matrix: &TransitionMatrix,
+    initial_state: StateType,
+    days: usize,
+    seed: u64,
+) -> Result<SimulationSummary, EngineError> {
+    validate_simulation_days(days).map_err(EngineError::InvalidInput)?;
+    let results = try_simulate_weather_with_rng(matrix, initial_state, days, &mut SeededRandom::new(seed))?;
+    
+    let states = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+    Ok(SimulationSummary {
+        simulation: simulation_days(&results, 0),
+        distribution: StateProbabilities::from_states(&states, &calculate_state_distribution(&results)),
+        average_streaks: StateProbabilities::from_states(&states, &calculate_average_streaks(&results)),
+    })
+}
+
+#[wasm_bindgen]
+pub fn simulate_with_stats(days: usize, initial_state_str: &str, seed: u32) -> Result<JsValue, JsValue> {
+    // Stateless alternative to run_simulation followed by get_statistics
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    let summary = simulate_and_summarize(matrix, initial_state, days, seed as u64)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    serde_wasm_bindgen::to_value(&summary)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize simulation summary: {}", e)))
+}
+
+// Helper function to build, validate and store the matrix for freshly parsed data
+// Any cached process_weather_data result no longer describes the active matrix
+fn train_and_store(historical_data: &HistoricalData) -> Result<MatrixData, String> {
+    // Call build_transition_matrix to generate transition matrix
+    let (matrix, warnings) = build_transition_matrix_checked(historical_data);
+    
+    // Validate the matrix is stochastic
+    if !matrix.is_stochastic() {
+        return Err("Generated transition matrix is not stochastic".to_string());
+    }
+    
+    // Store matrix and the history it came from for later access
+    *TRANSITION_MATRIX.lock().unwrap() = Some(matrix.clone());
+    *LAST_PROCESSED.lock().unwrap() = None;
+    *STORED_HISTORY.lock().unwrap() = Some(historical_data.clone());
+    
+    let mut matrix_data = MatrixData::from(&matrix);
+    matrix_data.warnings = warnings.iter().map(|w| w.to_string()).collect();
+    matrix_data.coverage = Some(historical_data.coverage(86400));
+    Ok(matrix_data)
+}
+
+#[wasm_bindgen]
+pub fn import_matrix(json_str: &str) -> Result<JsValue, JsValue> {
+    // Parse and validate a previously exported TransitionMatrix
+    let matrix = TransitionMatrix::from_json(json_str)
+        .map_err(|e| JsValue::from_str(&format!("Failed to import matrix: {}", e)))?;
+    
+    // Replace the active matrix; it wasn't trained on any stored history
+    *TRANSITION_MATRIX.lock().unwrap() = Some(matrix.clone());
+    *LAST_PROCESSED.lock().unwrap() = None;
+    *STORED_HISTORY.lock().unwrap() = None;
+    
+    serde_wasm_bindgen::to_value(&MatrixData::from(&matrix))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize matrix: {}", e)))
+}
+
+// Day 0 is the initial state, so `days = 7` yields a 7-element forecast including today
+#[wasm_bindgen]
+pub fn run_simulation(days: usize, initial_state_str: &str) -> Result<JsValue, JsValue> {
+    run_simulation_with(days, initial_state_str, &WasmSerializer)
+}
+
+// run_simulation with a pluggable output format
+pub fn run_simulation_with<S: ResultSerializer>(
+    days: usize,
+    initial_state_str: &str,
+    serializer: &S,
+) -> Result<S::Output, S::Error> {
+    validate_simulation_days(days)?;
+    
+    // Parse initial state string to StateType enum
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    // Retrieve stored transition matrix from static storage
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| "No transition matrix available. Call process_weather_data first.".to_string())?;
+    
+    // Simulate with browser entropy, surfacing a clean error if it's unavailable
+    let simulation_results = try_simulate_weather_with_rng(matrix, initial_state, days, &mut OsRandom)
+        .map_err(|e| e.to_string())?;
+    
+    // Store simulation results for statistics calculation
+    *SIMULATION_RESULTS.lock().unwrap() = Some(simulation_results.clone());
+    
+    serializer.serialize_simulation(&simulation_days(&simulation_results, 0))
+}
+
+#[wasm_bindgen]
+pub fn run_simulation_from_steady_state(days: usize, seed: u32) -> Result<JsValue, JsValue> {
+    validate_simulation_days(days).map_err(|e| JsValue::from_str(&e))?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    // Start from a state drawn from the steady state instead of a hand-picked one
+    let simulation_results = simulate_from_steady_state(matrix, days, seed as u64);
+    *SIMULATION_RESULTS.lock().unwrap() = Some(simulation_results.clone());
+    
+    serde_wasm_bindgen::to_value(&simulation_days(&simulation_results, 0))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize simulation results: {}", e)))
+}
+
+// Like run_simulation, but stops early once the chain is stuck in an absorbing state
+// for `stuck_days` days; absorbed_at tells the frontend where the weather froze
+#[wasm_bindgen]
+pub fn run_simulation_until_absorbed(days: usize, initial_state_str: &str, stuck_days: usize) -> Result<JsValue, JsValue> {
+    validate_simulation_days(days)?;
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    let outcome = simulate_until_absorbed(matrix, initial_state, days, stuck_days, &mut OsRandom)?;
+    *SIMULATION_RESULTS.lock().unwrap() = Some(outcome.results.clone());
+    
+    let data = AbsorbedSimulation {
+        simulation: simulation_days(&outcome.results, 0),
+        absorbed_at: outcome.absorbed_at,
+    };
+    serde_wasm_bindgen::to_value(&data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize simulation results: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn run_simulation_dated(
+    days: usize,
+    initial_state_str: &str,
+    start_timestamp: f64,
+    utc_offset_secs: i32,
+) -> Result<JsValue, JsValue> {
+    validate_simulation_days(days).map_err(|e| JsValue::from_str(&e))?;
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    // Simulate on UTC days starting at the given timestamp
+    let simulation_results = simulate_weather_dated(matrix, initial_state, days, start_timestamp as i64)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    
+    // Results are stored in UTC; the offset only shifts the emitted timestamps (no DST handling)
+    *SIMULATION_RESULTS.lock().unwrap() = Some(simulation_results.clone());
+    
+    serde_wasm_bindgen::to_value(&simulation_days(&simulation_results, utc_offset_secs as i64))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize simulation results: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn get_expected_first_occurrence(
+    initial_state_str: &str,
+    target_str: &str,
+    start_timestamp: f64,
+) -> Result<f64, JsValue> {
+    let initial_state = parse_initial_state(initial_state_str)?;
+    let target = target_str.parse::<StateType>()
+        .map_err(|_| JsValue::from_str(&format!("Invalid target state: {}. Must be 'Sunny', 'Rainy', or 'Cloudy'", target_str)))?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    let timestamp = expected_first_occurrence_date(matrix, initial_state, target, start_timestamp as i64)?;
+    Ok(timestamp as f64)
+}
+
+#[wasm_bindgen]
+pub fn get_statistics() -> Result<JsValue, JsValue> {
+    get_statistics_with(0, usize::MAX, &WasmSerializer)
+}
+
+// Same as get_statistics, but the distribution and streak statistics ignore the
+// first `burn_in` simulated days. Dropping the forced day-0 state (burn_in = 1) or a
+// longer transient gives a cleaner estimate of the chain's own behavior.
+#[wasm_bindgen]
+pub fn get_statistics_burn_in(burn_in: usize) -> Result<JsValue, JsValue> {
+    get_statistics_with(burn_in, usize::MAX, &WasmSerializer)
+}
+
+// Same as get_statistics, but the distribution and streak statistics only cover
+// simulated days start..end (end exclusive, clamped to the run), e.g. days 30-60 to
+// see the chain after it has mixed. Streaks are cut at the window edges.
+#[wasm_bindgen]
+pub fn get_statistics_range(start: usize, end: usize) -> Result<JsValue, JsValue> {
+    get_statistics_with(start, end, &WasmSerializer)
+}
+
+// Statistics for the stored matrix and the simulated days start..end, with a
+// pluggable output format
+pub fn get_statistics_with<S: ResultSerializer>(
+    start: usize,
+    end: usize,
+    serializer: &S,
+) -> Result<S::Output, S::Error> {
+    // Retrieve stored transition matrix
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| "No transition matrix available. Call process_weather_data first.".to_string())?;
+    
+    let simulation_guard = SIMULATION_RESULTS.lock().unwrap();
+    let simulation = simulation_guard.as_ref().map(|results| simulation_window(results, start, end));
+    
+    // Serialize all statistics to JsValue as structured object
+    serializer.serialize_statistics(&compute_statistics(matrix, simulation))
+}
+
+// Helper function to gather statistics for a matrix and an optional simulation run
+fn compute_statistics(matrix: &TransitionMatrix, simulation: Option<&[WeatherState]>) -> Statistics {
+    // Calculate steady-state distribution using calculate_steady_state
+    let steady_state = calculate_steady_state(matrix);
+    
+    // Compute state distribution from the simulation results
+    let state_distribution = if let Some(results) = simulation {
+        calculate_state_distribution(results)
+    } else {
+        // If no simulation has been run, return empty distribution
+        vec![0.0, 0.0, 0.0]
+    };
+    
+    // Calculate average streak lengths for each state
+    let average_streaks = if let Some(results) = simulation {
+        calculate_average_streaks(results)
+    } else {
+        vec![0.0, 0.0, 0.0]
+    };
+    
+    // The steady state follows the matrix's own (possibly hand-entered) state order, while
+    // the simulation helpers always report Sunny, Rainy, Cloudy
+    let weather_order = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+    Statistics {
+        steady_state: StateProbabilities::from_states(&matrix.states, &steady_state),
+        distribution: StateProbabilities::from_states(&weather_order, &state_distribution),
+        average_streaks: StateProbabilities::from_states(&weather_order, &average_streaks),
+        period: period(matrix),
+        predictability_improvement: information_gain(matrix),
+        persistence_index: persistence_index(matrix),
+        steady_state_nonuniformity: steady_state_nonuniformity(matrix),
+        kemeny_constant: kemeny_constant(matrix),
+        is_reversible: is_reversible(matrix),
+        reversibility_defect: reversibility_defect(matrix),
+        warnings: statistics_warnings(matrix),
+    }
+}
+
+// Helper function collecting numerical diagnostics for the statistics output
+fn statistics_warnings(matrix: &TransitionMatrix) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if condition_number(matrix) > ILL_CONDITIONED_THRESHOLD {
+        warnings.push(
+            "This matrix is numerically ill-conditioned; steady-state results may be unreliable".to_string()
+        );
+    }
+    warnings
+}
+
+#[wasm_bindgen]
+pub fn get_steady_state_detail() -> Result<JsValue, JsValue> {
+    // Steady state plus the diagnostics needed to show how far it can be trusted
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    let result = steady_state_auto(matrix);
+    let detail = SteadyStateDetail {
+        distribution: StateProbabilities::from_states(&matrix.states, &result.distribution),
+        converged: result.converged,
+        iterations: result.iterations,
+        method: result.method.to_string(),
+    };
+    
+    serde_wasm_bindgen::to_value(&detail)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize steady state: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn engine_status() -> Result<JsValue, JsValue> {
+    // Read-only snapshot of what the engine currently holds
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let simulation_guard = SIMULATION_RESULTS.lock().unwrap();
+    
+    let status = EngineStatus {
+        has_matrix: matrix_guard.is_some(),
+        has_simulation: simulation_guard.is_some(),
+        num_states: matrix_guard.as_ref().map_or(0, |m| m.states.len()),
+        simulation_days: simulation_guard.as_ref().map_or(0, |r| r.len()),
+    };
+    
+    serde_wasm_bindgen::to_value(&status)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize engine status: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn export_simulation_csv(utc_offset_secs: i32) -> Result<String, JsValue> {
+    // Serialize the stored simulation results for spreadsheet import
+    let simulation_guard = SIMULATION_RESULTS.lock().unwrap();
+    let results = simulation_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No simulation results available. Call run_simulation first."))?;
+    
+    Ok(simulation_to_csv_with_offset(results, utc_offset_secs as i64))
+}
+
+#[wasm_bindgen]
+pub fn export_mermaid(min_prob: f64) -> Result<String, JsValue> {
+    // Mermaid source for the stored chain, ready to paste into a markdown code block
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    Ok(to_mermaid(matrix, min_prob))
+}
+
+#[wasm_bindgen]
+pub fn set_matrix(rows: JsValue, state_names: Vec<String>) -> Result<(), JsValue> {
+    // Accept a hand-entered matrix as an array of rows, with no weather data involved
+    let rows: Vec<Vec<f64>> = serde_wasm_bindgen::from_value(rows)
+        .map_err(|e| JsValue::from_str(&format!("Matrix rows must be an array of number arrays: {}", e)))?;
+    
+    let matrix = TransitionMatrix::from_rows(rows, &state_names)
+        .map_err(|e| JsValue::from_str(&format!("Invalid matrix: {}", e)))?;
+    
+    // Replace the active matrix; old simulation results no longer apply
+    *TRANSITION_MATRIX.lock().unwrap() = Some(matrix);
+    *SIMULATION_RESULTS.lock().unwrap() = None;
+    *LAST_PROCESSED.lock().unwrap() = None;
+    *STORED_HISTORY.lock().unwrap() = None;
+    
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn set_default_state(state_str: &str) -> Result<(), JsValue> {
+    // Only unmatched condition strings are affected; keyword rules still win
+    let state = state_str.parse::<StateType>()
+        .map_err(|_| JsValue::from_str(&format!("Invalid default state: {}. Must be 'Sunny', 'Rainy', or 'Cloudy'", state_str)))?;
+    
+    *DEFAULT_STATE.lock().unwrap() = state;
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn classify_batch(conditions: Vec<String>) -> Result<JsValue, JsValue> {
+    // Classify every condition string in a single boundary crossing
+    let refs: Vec<&str> = conditions.iter().map(|c| c.as_str()).collect();
+    let default = *DEFAULT_STATE.lock().unwrap();
+    let names: Vec<String> = refs.iter()
+        .map(|c| classify_weather_with_default(c, default).to_string())
+        .collect();
+    
+    serde_wasm_bindgen::to_value(&names)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize classifications: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn get_forecast_distribution(
+    days: usize,
+    initial_state_str: &str,
+    include_entropy: bool,
+) -> Result<JsValue, JsValue> {
+    validate_simulation_days(days).map_err(|e| JsValue::from_str(&e))?;
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    // Label each day's exact distribution, optionally with its entropy
+    let forecast: Vec<ForecastEntry> = forecast_distribution(matrix, initial_state, days)?
+        .iter()
+        .enumerate()
+        .map(|(day, dist)| ForecastEntry {
+            day,
+            distribution: StateProbabilities::from_states(&matrix.states, dist),
+            entropy: include_entropy.then(|| distribution_entropy(dist)),
+        })
+        .collect();
+    
+    serde_wasm_bindgen::to_value(&forecast)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize forecast: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn get_forecast_days(
+    days: usize,
+    initial_state_str: &str,
+    start_timestamp: f64,
+) -> Result<JsValue, JsValue> {
+    validate_simulation_days(days).map_err(|e| JsValue::from_str(&e))?;
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    // Label each day's exact distribution with its date and entropy
+    let forecast = forecast_days(matrix, initial_state, days, start_timestamp as i64)?;
+    
+    serde_wasm_bindgen::to_value(&forecast)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize forecast: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn get_trend(current_str: &str) -> Result<JsValue, JsValue> {
+    let current = parse_initial_state(current_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    // Whatever doesn't improve or worsen stays on the same rung
+    let improve = improvement_probability(matrix, current)?;
+    let worsen = deterioration_probability(matrix, current)?;
+    let trend = Trend { improve, same: (1.0 - improve - worsen).max(0.0), worsen };
+    
+    serde_wasm_bindgen::to_value(&trend)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize trend: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn get_transition_asymmetry() -> Result<JsValue, JsValue> {
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    let asymmetries: Vec<TransitionAsymmetry> = transition_asymmetry(matrix)
+        .into_iter()
+        .map(|(from, to, difference)| TransitionAsymmetry {
+            from: from.to_string(),
+            to: to.to_string(),
+            difference,
+        })
+        .collect();
+    
+    serde_wasm_bindgen::to_value(&asymmetries)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize transition asymmetry: {}", e)))
+}
+
+#[wasm_bindgen]
+pub fn get_entropy_trajectory(days: usize, initial_state_str: &str) -> Result<Vec<f64>, JsValue> {
+    validate_simulation_days(days)?;
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    Ok(entropy_trajectory(matrix, initial_state, days)?)
+}
+
+#[wasm_bindgen]
+pub fn get_surprise(previous_str: &str, observed_str: &str) -> Result<f64, JsValue> {
+    let previous = parse_initial_state(previous_str)?;
+    let observed = observed_str.parse::<StateType>()
+        .map_err(|_| JsValue::from_str(&format!("Invalid observed state: {}. Must be 'Sunny', 'Rainy', or 'Cloudy'", observed_str)))?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    Ok(surprise(matrix, previous, observed)?)
+}
+
+#[wasm_bindgen]
+pub fn get_transition_support(from_str: &str, to_str: &str) -> Result<f64, JsValue> {
+    let from = parse_initial_state(from_str)?;
+    let to = to_str.parse::<StateType>()
+        .map_err(|_| JsValue::from_str(&format!("Invalid target state: {}. Must be 'Sunny', 'Rainy', or 'Cloudy'", to_str)))?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    transition_support(matrix, from, to)
+        .ok_or_else(|| JsValue::from_str("The active matrix has no observation counts (it was imported or entered by hand)"))
+}
+
+#[wasm_bindgen]
+pub fn get_tomorrow(current_str: &str) -> Result<JsValue, JsValue> {
+    let current = parse_initial_state(current_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    // Labeled { sunny, rainy, cloudy } odds for tomorrow
+    let tomorrow = StateProbabilities::from_states(&matrix.states, &next_state_distribution(matrix, current)?);
+    serde_wasm_bindgen::to_value(&tomorrow)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize distribution: {}", e)))
+}
+
+// Append one newly observed day to the stored history, retrain, and return tomorrow's
+// odds from that day, all in a single call for live tickers
+#[wasm_bindgen]
+pub fn observe_and_forecast(state_str: &str, timestamp: f64) -> Result<JsValue, JsValue> {
+    let state = parse_initial_state(state_str)?;
+    
+    // Extend a copy so a failed retrain leaves the stored history untouched
+    let mut history = STORED_HISTORY.lock().unwrap().clone()
+        .ok_or_else(|| JsValue::from_str("No stored history available. Call process_weather_data first."))?;
+    append_observation(&mut history, WeatherState::new(state, timestamp as i64))?;
+    
+    // Retraining stores the extended history with the new matrix and clears the processing cache
+    train_and_store(&history)?;
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    let forecast = ObservationForecast {
+        tomorrow: StateProbabilities::from_states(&matrix.states, &next_state_distribution(matrix, state)?),
+        total_observations: history.len(),
+    };
+    serde_wasm_bindgen::to_value(&forecast)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize forecast: {}", e)))
+}
+
+// Helper function adding a live observation, which must come after the last stored day
+fn append_observation(history: &mut HistoricalData, observation: WeatherState) -> Result<(), String> {
+    if let Some(last) = history.states.last()
+        && observation.timestamp <= last.timestamp
+    {
+        return Err(format!(
+            "Observation at {} is not after the last stored day at {}",
+            observation.timestamp, last.timestamp
+        ));
+    }
+    history.add_state(observation);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn get_expected_payoff(days: usize, initial_state_str: &str, payoffs: JsValue) -> Result<f64, JsValue> {
+    validate_simulation_days(days)?;
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    // Payoffs arrive as a { sunny, rainy, cloudy } object
+    let payoffs: StateProbabilities = serde_wasm_bindgen::from_value(payoffs)
+        .map_err(|e| JsValue::from_str(&format!("Payoffs must be a {{sunny, rainy, cloudy}} object: {}", e)))?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    Ok(expected_payoff(matrix, initial_state, &payoffs.to_states(&matrix.states), days)?)
+}
+
+#[wasm_bindgen]
+pub fn get_expected_transitions(days: usize, initial_state_str: &str) -> Result<f64, JsValue> {
+    validate_simulation_days(days)?;
+    let initial_state = parse_initial_state(initial_state_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    Ok(expected_transitions(matrix, initial_state, days)?)
+}
+
+#[wasm_bindgen]
+pub fn get_survival_probability(state_str: &str, days: usize) -> Result<f64, JsValue> {
+    validate_simulation_days(days)?;
+    let state = parse_initial_state(state_str)?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    Ok(survival_probability(matrix, state, days)?)
+}
+
+#[wasm_bindgen]
+pub fn get_best_day(target_str: &str, days: usize, initial_state_str: &str) -> Result<JsValue, JsValue> {
+    validate_simulation_days(days)?;
+    let initial_state = parse_initial_state(initial_state_str)?;
+    let target = target_str.parse::<StateType>()
+        .map_err(|_| JsValue::from_str(&format!("Invalid target state: {}. Must be 'Sunny', 'Rainy', or 'Cloudy'", target_str)))?;
+    
+    let matrix_guard = TRANSITION_MATRIX.lock().unwrap();
+    let matrix = matrix_guard.as_ref()
+        .ok_or_else(|| JsValue::from_str("No transition matrix available. Call process_weather_data first."))?;
+    
+    let (day, probability) = best_day_for(matrix, initial_state, target, days)?;
+    serde_wasm_bindgen::to_value(&BestDay { day, probability })
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize best day: {}", e)))
+}
+
+// Helper function to reject empty or excessively long simulations
+fn validate_simulation_days(days: usize) -> Result<(), String> {
+    if days == 0 {
+        return Err("Simulation days must be at least 1 (day 0 is the initial state)".to_string());
+    }
+    if days > MAX_SIMULATION_DAYS {
+        return Err(format!(
+            "Simulation days must not exceed {} (got {})",
+            MAX_SIMULATION_DAYS, days
+        ));
+    }
+    Ok(())
+}
+
+// Helper function to parse an initial state string for the bindings
+fn parse_initial_state(initial_state_str: &str) -> Result<StateType, String> {
+    initial_state_str.parse::<StateType>()
+        .map_err(|_| format!("Invalid initial state: {}. Must be 'Sunny', 'Rainy', or 'Cloudy'", initial_state_str))
+}
+
+// Helper function to convert simulation results into serializable days,
+// shifting timestamps by a fixed UTC offset
+fn simulation_days(results: &[WeatherState], utc_offset_secs: i64) -> Vec<SimulationDay> {
+    results.iter().enumerate().map(|(idx, ws)| {
+        SimulationDay {
+            day: idx,
+            state: ws.state.to_string(),
+            timestamp: ws.timestamp + utc_offset_secs,
+        }
+    }).collect()
+}
+
+// Helper structures for serialization
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixData {
+    pub matrix: Vec<f64>,
+    pub states: Vec<String>,
+    pub rows: usize,
+    pub cols: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    // Daily coverage of the training data, when the matrix was trained from observations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f64>,
+}
+
+impl From<&TransitionMatrix> for MatrixData {
+    fn from(matrix: &TransitionMatrix) -> Self {
+        MatrixData {
+            // Row-major order regardless of the array's memory layout
+            matrix: matrix.matrix.iter().copied().collect(),
+            states: matrix.states.iter().map(|s| s.to_string()).collect(),
+            rows: matrix.matrix.nrows(),
+            cols: matrix.matrix.ncols(),
+            warnings: Vec::new(),
+            coverage: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationDay {
+    pub day: usize,
+    pub state: String,
+    pub timestamp: i64,
+}
+
+// One day of an exact forecast, labeled for plotting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForecastDay {
+    pub day: usize,
+    pub timestamp: i64,
+    pub distribution: StateProbabilities,
+    // Uncertainty of the day's distribution in bits
+    pub entropy: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AbsorbedSimulation {
+    pub simulation: Vec<SimulationDay>,
+    pub absorbed_at: Option<usize>,
+}
+
+// P[from, to] - P[to, from] for one pair of states
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransitionAsymmetry {
+    pub from: String,
+    pub to: String,
+    pub difference: f64,
+}
+
+// Tomorrow's odds after a live observation, and how much history backs them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObservationForecast {
+    pub tomorrow: StateProbabilities,
+    pub total_observations: usize,
+}
+
+// Chance that tomorrow is better than, the same as, or worse than today
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trend {
+    pub improve: f64,
+    pub same: f64,
+    pub worsen: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BestDay {
+    pub day: usize,
+    pub probability: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateProbabilities {
+    pub sunny: f64,
+    pub rainy: f64,
+    pub cloudy: f64,
+}
+
+impl StateProbabilities {
+    // Label values by the state they belong to, regardless of matrix ordering
+    fn from_states(states: &[StateType], values: &[f64]) -> Self {
+        let mut probabilities = StateProbabilities { sunny: 0.0, rainy: 0.0, cloudy: 0.0 };
+        for (state, &value) in states.iter().zip(values.iter()) {
+            match state {
+                StateType::Sunny => probabilities.sunny = value,
+                StateType::Rainy => probabilities.rainy = value,
+                StateType::Cloudy => probabilities.cloudy = value,
+            }
+        }
+        probabilities
+    }
+
+    // Unpack values into the given state ordering
+    fn to_states(&self, states: &[StateType]) -> Vec<f64> {
+        states.iter().map(|state| match state {
+            StateType::Sunny => self.sunny,
+            StateType::Rainy => self.rainy,
+            StateType::Cloudy => self.cloudy,
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SteadyStateDetail {
+    pub distribution: StateProbabilities,
+    pub converged: bool,
+    pub iterations: usize,
+    pub method: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineStatus {
+    pub has_matrix: bool,
+    pub has_simulation: bool,
+    pub num_states: usize,
+    pub simulation_days: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ForecastEntry {
+    day: usize,
+    distribution: StateProbabilities,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entropy: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Statistics {
+    pub steady_state: StateProbabilities,
+    pub distribution: StateProbabilities,
+    pub average_streaks: StateProbabilities,
+    pub period: usize,
+    pub predictability_improvement: f64,
+    pub persistence_index: f64,
+    pub steady_state_nonuniformity: f64,
+    // Expected days to reach a steady-state-weighted random state, from any start
+    pub kemeny_constant: f64,
+    pub is_reversible: bool,
+    // Largest net probability flow between two states; 0 when reversible
+    pub reversibility_defect: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+// One simulation run together with the statistics computed from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationSummary {
+    pub simulation: Vec<SimulationDay>,
+    pub distribution: StateProbabilities,
+    pub average_streaks: StateProbabilities,
+}
+
+// Everything from one analysis run, for "download my results" exports and caching
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeatherReport {
+    pub location: String,
+    pub num_observations: usize,
+    pub matrix_data: MatrixData,
+    pub statistics: Statistics,
+    pub forecast: Vec<SimulationDay>,
+}
+
+// Output format for engine results, so computation is decoupled from presentation
+// Native users can implement this for MessagePack, Protobuf, etc.
+pub trait ResultSerializer {
+    type Output;
+    type Error: From<String>;
+
+    fn serialize_matrix(&self, matrix: &MatrixData) -> Result<Self::Output, Self::Error>;
+    fn serialize_simulation(&self, days: &[SimulationDay]) -> Result<Self::Output, Self::Error>;
+    fn serialize_statistics(&self, statistics: &Statistics) -> Result<Self::Output, Self::Error>;
+}
+
+// Serializes results to JSON strings
+pub struct JsonSerializer;
+
+impl ResultSerializer for JsonSerializer {
+    type Output = String;
+    type Error = String;
+
+    fn serialize_matrix(&self, matrix: &MatrixData) -> Result<String, String> {
+        serde_json::to_string(matrix).map_err(|e| format!("Failed to serialize matrix: {}", e))
+    }
+
+    fn serialize_simulation(&self, days: &[SimulationDay]) -> Result<String, String> {
+        serde_json::to_string(days).map_err(|e| format!("Failed to serialize simulation results: {}", e))
+    }
+
+    fn serialize_statistics(&self, statistics: &Statistics) -> Result<String, String> {
+        serde_json::to_string(statistics).map_err(|e| format!("Failed to serialize statistics: {}", e))
+    }
+}
+
+// Serializes results to JavaScript values via serde-wasm-bindgen
+pub struct WasmSerializer;
+
+impl ResultSerializer for WasmSerializer {
+    type Output = JsValue;
+    type Error = JsValue;
+
+    fn serialize_matrix(&self, matrix: &MatrixData) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(matrix)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize matrix: {}", e)))
+    }
+
+    fn serialize_simulation(&self, days: &[SimulationDay]) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(days)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize simulation results: {}", e)))
+    }
+
+    fn serialize_statistics(&self, statistics: &Statistics) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(statistics)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize statistics: {}", e)))
+    }
+}
+
+// Helper function to keep simulated days start..end, clamped to the results
+// An empty window (start at or past end) yields no days
+fn simulation_window(results: &[WeatherState], start: usize, end: usize) -> &[WeatherState] {
+    let end = end.min(results.len());
+    &results[start.min(end)..end]
+}
+
+// Helper function to calculate state distribution from simulation results
+fn calculate_state_distribution(results: &[WeatherState]) -> Vec<f64> {
+    let total = results.len() as f64;
+    if total == 0.0 {
+        return vec![0.0, 0.0, 0.0];
+    }
+    
+    let mut counts = [0.0, 0.0, 0.0];
+    for state in results {
+        match state.state {
+            StateType::Sunny => counts[0] += 1.0,
+            StateType::Rainy => counts[1] += 1.0,
+            StateType::Cloudy => counts[2] += 1.0,
+        }
+    }
+    
+    counts.iter().map(|&c| c / total).collect()
+}
+
+// Helper function to calculate average streak lengths for each state
+fn calculate_average_streaks(results: &[WeatherState]) -> Vec<f64> {
+    if results.is_empty() {
+        return vec![0.0, 0.0, 0.0];
+    }
+    
+    let states = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+    let mut average_streaks = vec![0.0, 0.0, 0.0];
+    
+    for (idx, &target_state) in states.iter().enumerate() {
+        let mut streak_lengths = Vec::new();
+        let mut current_streak = 0;
+        
+        for weather_state in results {
+            if weather_state.state == target_state {
+                current_streak += 1;
+            } else if current_streak > 0 {
+                streak_lengths.push(current_streak);
+                current_streak = 0;
+            }
+        }
+        
+        // Don't forget the last streak if it ends at the end of results
+        if current_streak > 0 {
+            streak_lengths.push(current_streak);
+        }
+        
+        // Calculate average
+        if !streak_lengths.is_empty() {
+            let sum: usize = streak_lengths.iter().sum();
+            average_streaks[idx] = sum as f64 / streak_lengths.len() as f64;
+        }
+    }
+    
+    average_streaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init() {
+        assert!(init_markov_engine().is_ok());
+    }
+
+    #[test]
+    fn test_from_json_rejects_invalid_entries() {
+        let valid = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.5, 0.25, 0.25,
+                0.2, 0.6, 0.2,
+                0.3, 0.3, 0.4,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let json = serde_json::to_string(&valid).unwrap();
+        assert!(TransitionMatrix::from_json(&json).is_ok());
+
+        let mut negative = valid.clone();
+        negative.matrix[[1, 0]] = -0.2;
+        negative.matrix[[1, 1]] = 1.0;
+        let json = serde_json::to_string(&negative).unwrap();
+        match TransitionMatrix::from_json(&json) {
+            Err(ParseError::InvalidData(msg)) => assert!(msg.contains("[1, 0]")),
+            other => panic!("expected InvalidData, got {:?}", other),
+        }
+
+        let mut nan = valid.clone();
+        nan.matrix[[2, 2]] = f64::NAN;
+        assert!(nan.validate_probabilities().is_err());
+    }
+
+    fn sample_matrix() -> TransitionMatrix {
+        TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.6, 0.1, 0.3,
+                0.2, 0.5, 0.3,
+                0.4, 0.3, 0.3,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        }
+    }
+
+    #[test]
+    fn test_commute_time_is_symmetric() {
+        let matrix = sample_matrix();
+        let states = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+        for &a in &states {
+            for &b in &states {
+                let ab = commute_time(&matrix, a, b).unwrap();
+                let ba = commute_time(&matrix, b, a).unwrap();
+                assert!(ab.is_finite());
+                assert!((ab - ba).abs() < 1e-9);
+            }
+        }
+
+        // Mean recurrence time equals 1 / steady-state probability
+        let steady = calculate_steady_state(&matrix);
+        let recurrence = mean_first_passage_time(&matrix, StateType::Sunny, StateType::Sunny).unwrap();
+        assert!((recurrence - 1.0 / steady[0]).abs() < 1e-6);
+
+        // An absorbing Sunny state can never reach Rainy again
+        let mut absorbing = matrix.clone();
+        absorbing.matrix.row_mut(0).assign(&ndarray::arr1(&[1.0, 0.0, 0.0]));
+        assert!(commute_time(&absorbing, StateType::Sunny, StateType::Rainy).unwrap().is_infinite());
+    }
+
+    #[test]
+    fn test_steady_state_auto_picks_method() {
+        let matrix = sample_matrix();
+        let result = steady_state_auto(&matrix);
+        assert_eq!(result.method, SteadyStateMethod::LinearSolve);
+        assert!(result.converged && !result.is_approximate());
+        assert_eq!(result.iterations, 0);
+        let approx = calculate_steady_state(&matrix);
+        for (exact, approx) in result.distribution.iter().zip(approx.iter()) {
+            assert!((exact - approx).abs() < 1e-6);
+        }
+
+        // Two absorbing states make the chain reducible
+        let reducible = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                1.0, 0.0, 0.0,
+                0.0, 1.0, 0.0,
+                0.5, 0.25, 0.25,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let result = steady_state_auto(&reducible);
+        assert_eq!(result.method, SteadyStateMethod::PowerIteration);
+        assert!(result.is_approximate());
+        assert!(result.converged && result.iterations > 0);
+        assert_eq!(result.method.to_string(), "PowerIteration");
+    }
+
+    #[test]
+    fn test_simulation_to_csv() {
+        let results = vec![
+            WeatherState::new(StateType::Sunny, 0),
+            WeatherState::new(StateType::Rainy, 86400),
+            WeatherState::new(StateType::Cloudy, parse_date_to_timestamp("2024-03-01").unwrap()),
+        ];
+        let csv = simulation_to_csv(&results);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "day,state,timestamp,date");
+        assert_eq!(lines[1], "0,Sunny,0,1970-01-01");
+        assert_eq!(lines[2], "1,Rainy,86400,1970-01-02");
+        assert!(lines[3].ends_with(",2024-03-01"));
+    }
+
+    #[test]
+    fn test_period() {
+        assert_eq!(period(&sample_matrix()), 1);
+
+        // Deterministic Sunny <-> Rainy oscillation
+        let oscillating = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.0, 1.0, 0.0,
+                1.0, 0.0, 0.0,
+                0.5, 0.5, 0.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        assert_eq!(period(&oscillating), 2);
+        assert_eq!(state_period(&oscillating, StateType::Cloudy).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_utc_offset_crosses_day_boundary() {
+        let start = parse_date_to_timestamp("2024-06-10").unwrap();
+        let results = simulate_weather_dated(&sample_matrix(), StateType::Sunny, 2, start).unwrap();
+        assert_eq!(results[1].timestamp, start + 86400);
+
+        // A negative offset pushes UTC midnight back into the previous local day
+        let csv = simulation_to_csv_with_offset(&results, -5 * 3600);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[1].ends_with(",2024-06-09"));
+        assert!(lines[2].ends_with(",2024-06-10"));
+    }
+
+    #[test]
+    fn test_identity_and_n_step_matrix() {
+        let states = vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+        let identity = TransitionMatrix::identity(states.clone());
+        assert!(identity.is_stochastic());
+
+        let matrix = sample_matrix();
+        assert_eq!(n_step_matrix(&matrix, 0).matrix, identity.matrix);
+        assert_eq!(n_step_matrix(&matrix, 1).matrix, matrix.matrix);
+        assert!(n_step_matrix(&matrix, 5).is_stochastic());
+    }
+
+    #[test]
+    fn test_cesaro_limit_on_periodic_chain() {
+        let oscillating = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.0, 1.0, 0.0,
+                1.0, 0.0, 0.0,
+                0.5, 0.5, 0.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let limit = cesaro_limit(&oscillating, 100);
+        assert!((limit[0] - 0.5).abs() < 1e-9);
+        assert!((limit[1] - 0.5).abs() < 1e-9);
+
+        // Aperiodic chains agree with the stationary distribution
+        let matrix = sample_matrix();
+        let limit = cesaro_limit(&matrix, 5000);
+        let exact = steady_state_exact(&matrix).unwrap();
+        for (a, b) in limit.iter().zip(exact.iter()) {
+            assert!((a - b).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_validate_simulation_days() {
+        assert!(validate_simulation_days(0).is_err());
+        assert!(validate_simulation_days(1).is_ok());
+        assert!(validate_simulation_days(MAX_SIMULATION_DAYS).is_ok());
+        assert!(validate_simulation_days(MAX_SIMULATION_DAYS + 1).is_err());
+    }
+
+    #[test]
+    fn test_distribution_entropy() {
+        assert_eq!(distribution_entropy(&[1.0, 0.0, 0.0]), 0.0);
+        assert!((distribution_entropy(&[0.5, 0.5, 0.0]) - 1.0).abs() < 1e-12);
+
+        // Forecast entropy starts at zero (today is known) and grows
+        let forecast = forecast_distribution(&sample_matrix(), StateType::Sunny, 3).unwrap();
+        assert_eq!(forecast.len(), 3);
+        assert_eq!(distribution_entropy(&forecast[0]), 0.0);
+        assert!(distribution_entropy(&forecast[1]) > 0.0);
+    }
+
+    #[test]
+    fn test_from_rows() {
+        let names: Vec<String> = ["Sunny", "Rainy", "Cloudy"].iter().map(|s| s.to_string()).collect();
+        let matrix = TransitionMatrix::from_rows(vec![
+            vec![0.5, 0.2, 0.3],
+            vec![0.3, 0.3, 0.4],
+            vec![0.3333, 0.3333, 0.3333],
+        ], &names).unwrap();
+        assert!(matrix.is_stochastic());
+
+        assert!(TransitionMatrix::from_rows(vec![vec![0.5, 0.5]], &names).is_err());
+        assert!(TransitionMatrix::from_rows(vec![
+            vec![0.9, 0.2, 0.3],
+            vec![0.3, 0.3, 0.4],
+            vec![0.2, 0.4, 0.4],
+        ], &names).is_err());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let matrix = sample_matrix();
+        let bytes = matrix.to_bytes();
+        let decoded = TransitionMatrix::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.matrix, matrix.matrix);
+        assert_eq!(decoded.states, matrix.states);
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        assert!(TransitionMatrix::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn test_sample_index() {
+        let mut rng = SeededRandom::new(42);
+        for _ in 0..100 {
+            assert_eq!(sample_index(&[0.0, 1.0, 0.0], &mut rng), 1);
+        }
+
+        let mut counts = [0usize; 3];
+        for _ in 0..10_000 {
+            counts[sample_index(&[0.2, 0.3, 0.5], &mut rng)] += 1;
+        }
+        assert!((counts[0] as f64 / 10_000.0 - 0.2).abs() < 0.03);
+        assert!((counts[2] as f64 / 10_000.0 - 0.5).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_recommended_sample_size() {
+        // Classic +/-5% at 95% confidence
+        assert_eq!(recommended_sample_size(0.05, 0.95), 385);
+        assert!(recommended_sample_size(0.05, 0.99) > 385);
+        assert_eq!(recommended_sample_size(0.0, 0.95), 0);
+    }
+
+    fn sample_weather_json() -> String {
+        let conditions = ["Sunny", "Sunny", "Light rain", "Overcast", "Sunny", "Patchy rain", "Cloudy"];
+        let days: Vec<String> = conditions.iter().enumerate().map(|(i, c)| {
+            format!(r#"{{"date": "2024-06-{:02}", "day": {{"condition": {{"text": "{}"}}}}}}"#, i + 1, c)
+        }).collect();
+        format!(
+            r#"{{"location": {{"name": "London"}}, "forecast": {{"forecastday": [{}]}}}}"#,
+            days.join(",")
+        )
+    }
+
+    #[test]
+    fn test_json_serializer() {
+        let output = process_weather_data_with(&sample_weather_json(), true, &JsonSerializer).unwrap();
+        let data: MatrixData = serde_json::from_str(&output).unwrap();
+        assert_eq!(data.rows, 3);
+        assert_eq!(data.states, vec!["Sunny", "Rainy", "Cloudy"]);
+
+        let err = process_weather_data_with("not json", false, &JsonSerializer).unwrap_err();
+        assert!(err.starts_with("Failed to parse weather data"));
+    }
+
+    #[test]
+    fn test_processing_hash() {
+        let json = sample_weather_json();
+        assert_eq!(processing_hash(&json, StateType::Cloudy), processing_hash(&json, StateType::Cloudy));
+        assert_ne!(processing_hash(&json, StateType::Cloudy), processing_hash(&json, StateType::Sunny));
+        assert_ne!(processing_hash(&json, StateType::Cloudy), processing_hash("{}", StateType::Cloudy));
+    }
+
+    #[test]
+    fn test_simulate_with_bias() {
+        let matrix = sample_matrix();
+        let uniform = [1.0 / 3.0; 3];
+
+        // No bias reproduces the plain chain for the same seed
+        let plain = simulate_weather_with_rng(&matrix, StateType::Sunny, 50, &mut SeededRandom::new(7));
+        let biased = simulate_with_bias(&matrix, StateType::Sunny, 50, &uniform, 0.0, &mut SeededRandom::new(7)).unwrap();
+        let plain_states: Vec<StateType> = plain.iter().map(|w| w.state).collect();
+        let biased_states: Vec<StateType> = biased.iter().map(|w| w.state).collect();
+        assert_eq!(plain_states, biased_states);
+
+        // Full bias toward Rainy forces every following day to be Rainy
+        let forced = simulate_with_bias(&matrix, StateType::Sunny, 20, &[0.0, 1.0, 0.0], 1.0, &mut SeededRandom::new(1)).unwrap();
+        assert!(forced[1..].iter().all(|w| w.state == StateType::Rainy));
+
+        assert!(simulate_with_bias(&matrix, StateType::Sunny, 5, &[1.0], 0.5, &mut SeededRandom::new(1)).is_err());
+
+        // Random failures surface as errors, and the initial state is checked even for one day
+        let err = simulate_with_bias(&matrix, StateType::Sunny, 5, &uniform, 0.5, &mut NoEntropy).unwrap_err();
+        assert!(err.starts_with("Random number generation unavailable"));
+        let two_state = TransitionMatrix {
+            matrix: Array2::from_shape_vec((2, 2), vec![0.9, 0.1, 0.3, 0.7]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy],
+            counts: None,
+        };
+        assert!(simulate_with_bias(&two_state, StateType::Cloudy, 1, &[0.5, 0.5], 0.5, &mut SeededRandom::new(1)).is_err());
+    }
+
+    #[test]
+    fn test_expected_payoff() {
+        let matrix = sample_matrix();
+        // Day 0 is Sunny for certain; day 1 follows the Sunny row
+        let payoff = expected_payoff(&matrix, StateType::Sunny, &[10.0, -5.0, 0.0], 2).unwrap();
+        assert!((payoff - (10.0 + 0.6 * 10.0 - 0.1 * 5.0)).abs() < 1e-12);
+        assert!(expected_payoff(&matrix, StateType::Sunny, &[1.0, 2.0], 2).is_err());
+    }
+
+    #[test]
+    fn test_single_distinct_state_warning() {
+        let mut data = HistoricalData::new("Desert".to_string());
+        for day in 0..5 {
+            data.add_state(WeatherState::new(StateType::Sunny, day * 86400));
+        }
+        assert_eq!(data.distinct_states(), 1);
+
+        let (matrix, warnings) = build_transition_matrix_checked(&data);
+        assert!(matrix.is_stochastic());
+        assert_eq!(warnings, vec![DataWarning::TooFewDistinctStates(1)]);
+
+        data.add_state(WeatherState::new(StateType::Rainy, 5 * 86400));
+        assert!(build_transition_matrix_checked(&data).1.is_empty());
+    }
+
+    #[test]
+    fn test_incremental_model_matches_batch() {
+        let historical_data = parse_weather_data(&sample_weather_json()).unwrap();
+        let mut model = IncrementalModel::new();
+        for observation in historical_data.iter() {
+            model.update_with(observation);
+        }
+        assert_eq!(model.matrix().matrix, build_transition_matrix(&historical_data).matrix);
+        assert_eq!(model.counts(), &transition_counts(&historical_data));
+        assert_eq!(model.matrix().counts.as_ref(), Some(model.counts()));
+
+        // Warm-started refinement agrees with the exact answer after a small update
+        let exact_before = model.steady_state(true);
+        model.update_with(&WeatherState::new(StateType::Rainy, 0));
+        let refined = model.steady_state(false);
+        let exact = steady_state_auto(model.matrix()).distribution;
+        assert_ne!(exact_before, exact);
+        for (a, b) in refined.iter().zip(exact.iter()) {
+            assert!((a - b).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn test_parse_multi_location() {
+        let single = sample_weather_json();
+        let json = format!("[{}, {}]", single, single.replace("London", "Paris"));
+        let datasets = parse_multi_location(&json).unwrap();
+        assert_eq!(datasets.len(), 2);
+
+        let models = train_all(&datasets);
+        assert_eq!(models[0].0, "London");
+        assert_eq!(models[1].0, "Paris");
+        assert!(models.iter().all(|(_, m)| m.is_stochastic()));
+
+        assert!(parse_multi_location(&single).is_err());
+    }
+
+    #[test]
+    fn test_hitting_probability() {
+        let matrix = sample_matrix();
+        assert_eq!(hitting_probability(&matrix, StateType::Sunny, StateType::Rainy, 0).unwrap(), 0.0);
+        assert_eq!(hitting_probability(&matrix, StateType::Rainy, StateType::Rainy, 0).unwrap(), 1.0);
+        assert!((hitting_probability(&matrix, StateType::Sunny, StateType::Rainy, 1).unwrap() - 0.1).abs() < 1e-12);
+
+        // Two days: rain tomorrow, or not tomorrow but the day after
+        let expected = 0.1 + 0.6 * 0.1 + 0.3 * 0.3;
+        assert!((hitting_probability(&matrix, StateType::Sunny, StateType::Rainy, 2).unwrap() - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_stationarity_score() {
+        // Alternating weather, then a long sunny spell
+        let mut data = HistoricalData::new("Test".to_string());
+        for day in 0..40 {
+            let state = if day < 20 {
+                if day % 2 == 0 { StateType::Sunny } else { StateType::Rainy }
+            } else {
+                StateType::Sunny
+            };
+            data.add_state(WeatherState::new(state, day * 86400));
+        }
+        assert!(stationarity_score(&data, 0) > 0.3);
+
+        let steady: Vec<WeatherState> = data.states[..20].to_vec();
+        data.states = steady;
+        assert!(stationarity_score(&data, 10) < 1e-12);
+    }
+
+    #[test]
+    fn test_include_current() {
+        let options = ParseOptions { include_current: true, ..ParseOptions::default() };
+
+        // Current day overlaps the first forecast day: replaced, not duplicated
+        let json = sample_weather_json().replacen(
+            r#""location""#,
+            r#""current": {"last_updated_epoch": 1717250000, "last_updated": "2024-06-01 14:00", "condition": {"text": "Heavy rain"}}, "location""#,
+            1,
+        );
+        let data = parse_weather_data_with_options(&json, &options).unwrap();
+        assert_eq!(data.len(), 7);
+        assert_eq!(data.states[0].state, StateType::Rainy);
+
+        // A current observation after the forecast is appended in order
+        let json = sample_weather_json().replacen(
+            r#""location""#,
+            r#""current": {"last_updated_epoch": 1720000000, "condition": {"text": "Clear"}}, "location""#,
+            1,
+        );
+        let data = parse_weather_data_with_options(&json, &options).unwrap();
+        assert_eq!(data.len(), 8);
+        assert_eq!(data.states[7].timestamp, 1720000000);
+        assert_eq!(parse_weather_data(&json).unwrap().len(), 7);
+    }
+
+    #[test]
+    fn test_information_gain() {
+        // Independent days: every row equals the steady state, so today tells us nothing
+        let independent = TransitionMatrix::from_rows(
+            vec![vec![0.5, 0.3, 0.2]; 3],
+            &["Sunny".to_string(), "Rainy".to_string(), "Cloudy".to_string()],
+        ).unwrap();
+        assert!(information_gain(&independent).abs() < 1e-9);
+
+        // Deterministic persistence: tomorrow is fully known from today
+        let identity = TransitionMatrix::identity(vec![StateType::Sunny, StateType::Rainy]);
+        assert_eq!(entropy_rate(&identity), 0.0);
+        assert!(information_gain(&sample_matrix()) > 0.0);
+    }
+
+    struct FixedRandom(f64);
+
+    impl RandomSource for FixedRandom {
+        fn next_f64(&mut self) -> f64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_normalize_rows_repairs_drift() {
+        let mut drifted = sample_matrix();
+        drifted.matrix[[0, 2]] += 3e-6;
+        drifted.matrix[[1, 2]] -= 3e-6;
+        assert!(!drifted.is_stochastic());
+
+        // Row 0 sums to 1 + 3e-6, so its Sunny boundary sits at 0.6 until it is rescaled
+        let just_below = 0.6 - 1e-6;
+        assert_eq!(sample_index(drifted.matrix.row(0).as_slice().unwrap(), &mut FixedRandom(just_below)), 0);
+
+        drifted.normalize_rows();
+        assert!(drifted.is_stochastic());
+        for row in drifted.matrix.rows() {
+            assert!((row.sum() - 1.0).abs() < 1e-12);
+        }
+        // Rescaling moves the boundary to 0.6 / (1 + 3e-6), below that value
+        assert!((drifted.matrix[[0, 0]] - 0.6 / (1.0 + 3e-6)).abs() < 1e-15);
+        assert_eq!(sample_index(drifted.matrix.row(0).as_slice().unwrap(), &mut FixedRandom(just_below)), 1);
+    }
+
+    #[test]
+    fn test_state_correlation() {
+        let london = parse_weather_data(&sample_weather_json()).unwrap();
+        assert!((state_correlation(&london, &london).unwrap() - 1.0).abs() < 1e-12);
+
+        let mut elsewhere = london.clone();
+        elsewhere.states.truncate(2);
+        assert!(state_correlation(&london, &elsewhere).is_err());
+    }
+
+    #[test]
+    fn test_simulation_iter_matches_eager() {
+        let matrix = sample_matrix();
+        let eager = simulate_weather_with_rng(&matrix, StateType::Cloudy, 30, &mut SeededRandom::new(9));
+        let lazy: Vec<WeatherState> = SimulationIter::with_rng(&matrix, StateType::Cloudy, SeededRandom::new(9)).unwrap()
+            .take(30)
+            .collect();
+        assert_eq!(lazy.len(), eager.len());
+        for (a, b) in lazy.iter().zip(eager.iter()) {
+            assert_eq!(a.state, b.state);
+            assert_eq!(a.timestamp, b.timestamp);
+        }
+    }
+
+    #[test]
+    fn test_mixing_time_worst_case() {
+        let matrix = sample_matrix();
+        let worst = mixing_time_worst_case(&matrix, 1e-3).unwrap();
+        for &state in &matrix.states {
+            assert!(mixing_time(&matrix, state, 1e-3).unwrap() <= worst);
+        }
+
+        let oscillating = TransitionMatrix::from_rows(
+            vec![vec![0.0, 1.0, 0.0], vec![1.0, 0.0, 0.0], vec![0.5, 0.5, 0.0]],
+            &["Sunny".to_string(), "Rainy".to_string(), "Cloudy".to_string()],
+        ).unwrap();
+        assert!(mixing_time_worst_case(&oscillating, 1e-3).is_err());
+    }
+
+    #[test]
+    fn test_out_of_order_dates() {
+        let json = sample_weather_json().replace("2024-06-03", "2024-06-09");
+        match parse_weather_data(&json) {
+            Err(ParseError::InvalidData(msg)) => assert!(msg.contains("2024-06-04")),
+            other => panic!("expected InvalidData, got {:?}", other),
+        }
+
+        let options = ParseOptions { sort_by_date: true, ..ParseOptions::default() };
+        let data = parse_weather_data_with_options(&json, &options).unwrap();
+        assert!(data.states.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+        assert_eq!(data.states.last().unwrap().state, StateType::Rainy);
+    }
+
+    #[test]
+    fn test_reverse_matrix() {
+        // Birth-death chains are reversible: reversing gives the same matrix back
+        let birth_death = TransitionMatrix::from_rows(
+            vec![vec![0.7, 0.3, 0.0], vec![0.2, 0.5, 0.3], vec![0.0, 0.4, 0.6]],
+            &["Sunny".to_string(), "Cloudy".to_string(), "Rainy".to_string()],
+        ).unwrap();
+        let reversed = reverse_matrix(&birth_death).unwrap();
+        for (a, b) in reversed.matrix.iter().zip(birth_death.matrix.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        assert!(reverse_matrix(&sample_matrix()).unwrap().is_stochastic());
+
+        let mut absorbing = sample_matrix();
+        absorbing.matrix.row_mut(0).assign(&ndarray::arr1(&[1.0, 0.0, 0.0]));
+        assert!(reverse_matrix(&absorbing).is_err());
+    }
+
+    #[test]
+    fn test_count_histogram() {
+        let matrix = sample_matrix();
+        let histogram = count_histogram(&matrix, StateType::Sunny, 30, 200, 5);
+        assert_eq!(histogram.len(), 3);
+        for per_state in &histogram {
+            assert_eq!(per_state.len(), 31);
+            assert_eq!(per_state.iter().sum::<usize>(), 200);
+        }
+        // Day 0 is always Sunny
+        assert_eq!(histogram[0][0], 0);
+        assert_eq!(histogram, count_histogram(&matrix, StateType::Sunny, 30, 200, 5));
+    }
+
+    #[test]
+    fn test_collapse_repeats() {
+        let options = ParseOptions { collapse_repeats: true, ..ParseOptions::default() };
+        let data = parse_weather_data_with_options(&sample_weather_json(), &options).unwrap();
+        // Sunny, Sunny, Rainy, ... collapses the leading pair into the first day
+        assert_eq!(data.len(), 6);
+        assert_eq!(data.states[0].timestamp, parse_date_to_timestamp("2024-06-01").unwrap());
+        assert!(data.state_pairs().all(|(a, b)| a.state != b.state));
+    }
+
+    #[test]
+    fn test_history_probability() {
+        let matrix = sample_matrix();
+        let mut data = HistoricalData::new("Test".to_string());
+        data.add_state(WeatherState::new(StateType::Sunny, 0));
+        assert_eq!(history_probability(&matrix, &data).unwrap(), 1.0);
+
+        data.add_state(WeatherState::new(StateType::Rainy, 86400));
+        data.add_state(WeatherState::new(StateType::Rainy, 2 * 86400));
+        let probability = history_probability(&matrix, &data).unwrap();
+        assert!((probability - 0.1 * 0.5).abs() < 1e-12);
+        assert!((log_likelihood(&matrix, &data).unwrap() - probability.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_best_initial_state() {
+        let matrix = sample_matrix();
+        // Sunny has the strongest Sunny self-transition in the sample matrix
+        assert_eq!(best_initial_state(&matrix, StateType::Sunny, 1, Objective::MaximizeExpectedDays).unwrap(), StateType::Sunny);
+        // Rain is most likely tomorrow when it is already raining
+        let (best, scores) = best_initial_state_scored(&matrix, StateType::Rainy, 1, Objective::MaximizeHittingProbability).unwrap();
+        assert_eq!(scores.len(), 3);
+        assert!((scores[1].1 - 0.5).abs() < 1e-12);
+        assert_eq!(best, StateType::Rainy);
+    }
+
+    struct NoEntropy;
+
+    impl RandomSource for NoEntropy {
+        fn next_f64(&mut self) -> f64 {
+            panic!("no entropy");
+        }
+
+        fn try_next_f64(&mut self) -> Result<f64, RandomError> {
+            Err(RandomError("crypto unavailable".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_random_failure_is_reported() {
+        let err = try_simulate_weather_with_rng(&sample_matrix(), StateType::Sunny, 5, &mut NoEntropy).unwrap_err();
+        assert!(err.to_string().starts_with("Random number generation unavailable"));
+
+        // A single day needs no random numbers
+        assert!(try_simulate_weather_with_rng(&sample_matrix(), StateType::Sunny, 1, &mut NoEntropy).is_ok());
+    }
+
+    #[test]
+    fn test_condition_codes_take_precedence() {
+        assert_eq!(classify_by_code(1000), StateType::Sunny);
+        assert_eq!(classify_by_code(1009), StateType::Cloudy);
+        assert_eq!(classify_by_code(1195), StateType::Rainy);
+        assert_eq!(weather_code_state(9999), None);
+
+        // Localized text with a known code, and an unknown code that falls back to text
+        let json = r#"{"forecast": {"forecastday": [
+            {"date": "2024-06-01", "day": {"condition": {"text": "Soleado", "code": 1000}}},
+            {"date": "2024-06-02", "day": {"condition": {"text": "Light rain", "code": 4242}}},
+            {"date": "2024-06-03", "day": {"condition": {"text": "Sunny"}}}
+        ]}, "location": {"name": "Madrid"}}"#;
+        let data = parse_weather_data(json).unwrap();
+        let states: Vec<StateType> = data.states.iter().map(|w| w.state).collect();
+        assert_eq!(states, vec![StateType::Sunny, StateType::Rainy, StateType::Sunny]);
+    }
+
+    #[test]
+    fn test_autocorrelation() {
+        // Alternating Sunny/Rainy is perfectly anti-correlated at lag 1 and correlated at lag 2
+        let mut data = HistoricalData::new("Test".to_string());
+        for i in 0..6 {
+            let state = if i % 2 == 0 { StateType::Sunny } else { StateType::Rainy };
+            data.add_state(WeatherState::new(state, i * 86400));
+        }
+        let acf = autocorrelation(&data, 10);
+        assert_eq!(acf.len(), 4);
+        assert!((acf[0] + 1.0).abs() < 1e-12);
+        assert!((acf[1] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_default_state_for_unknown_conditions() {
+        assert_eq!(classify_weather_with_default("Smoke", StateType::Sunny), StateType::Sunny);
+        // Keyword rules still take precedence over the default
+        assert_eq!(classify_weather_with_default("Light drizzle", StateType::Sunny), StateType::Rainy);
+        assert_eq!(classify_weather("Smoke"), StateType::Cloudy);
+
+        let json = r#"{"forecast": {"forecastday": [
+            {"date": "2024-06-01", "day": {"condition": {"text": "Dust"}}},
+            {"date": "2024-06-02", "day": {"condition": {"text": "Cloudy"}}}
+        ]}, "location": {"name": "Cairo"}}"#;
+        let options = ParseOptions { default_state: Some(StateType::Sunny), ..Default::default() };
+        let data = parse_weather_data_with_options(json, &options).unwrap();
+        assert_eq!(data.states[0].state, StateType::Sunny);
+    }
+
+    #[test]
+    fn test_expected_transitions() {
+        let matrix = sample_matrix();
+        assert_eq!(expected_transitions(&matrix, StateType::Sunny, 1).unwrap(), 0.0);
+        // One step from Sunny leaves with probability 1 - 0.6
+        assert!((expected_transitions(&matrix, StateType::Sunny, 2).unwrap() - 0.4).abs() < 1e-12);
+
+        let identity = TransitionMatrix::identity(matrix.states.clone());
+        assert_eq!(expected_transitions(&identity, StateType::Rainy, 30).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_clip_probabilities() {
+        let mut matrix = TransitionMatrix::identity(vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy]);
+        clip_probabilities(&mut matrix, 0.01);
+        assert!(matrix.is_stochastic());
+        assert!(matrix.matrix.iter().all(|&p| p > 0.0 && p < 1.0));
+        assert!((matrix.matrix[[0, 1]] - 0.01 / 1.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_survival_probability_matches_streak_distribution() {
+        let matrix = sample_matrix();
+        let stay = matrix.matrix[[0, 0]];
+        assert_eq!(survival_probability(&matrix, StateType::Sunny, 0).unwrap(), 1.0);
+
+        // A run longer than `days` is the complement of the geometric streak CDF
+        let days = 7;
+        let cumulative: f64 = (1..=days).map(|len| stay.powi(len - 1) * (1.0 - stay)).sum();
+        let survival = survival_probability(&matrix, StateType::Sunny, days as usize).unwrap();
+        assert!((survival - (1.0 - cumulative)).abs() < 1e-12);
+
+        // Horizons past i32::MAX decay toward 0 instead of wrapping to a negative exponent
+        assert_eq!(survival_probability(&matrix, StateType::Sunny, usize::MAX).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_custom_classifier() {
+        let json = sample_weather_json();
+        let data = parse_weather_data_with_classifier(&json, &ParseOptions::default(), |text| {
+            Ok(if text.contains("rain") { StateType::Rainy } else { StateType::Sunny })
+        }).unwrap();
+        assert_eq!(data.states[2].state, StateType::Rainy);
+        assert_eq!(data.states[3].state, StateType::Sunny);
+
+        let err = parse_weather_data_with_classifier(&json, &ParseOptions::default(), |text| {
+            Err(format!("no rule for {}", text))
+        }).unwrap_err();
+        assert!(err.to_string().contains("no rule for Sunny"));
+    }
+
+    #[test]
+    fn test_steady_state_sensitivity_matches_finite_difference() {
+        let matrix = sample_matrix();
+        let sensitivity = steady_state_sensitivity(&matrix).unwrap();
+        let base = steady_state_exact(&matrix).unwrap();
+        let step = 1e-6;
+
+        for i in 0..3 {
+            for j in 0..3 {
+                let mut nudged = matrix.clone();
+                nudged.matrix[[i, j]] += step;
+                nudged.normalize_rows();
+                let shifted = steady_state_exact(&nudged).unwrap();
+                for k in 0..3 {
+                    let numeric = (shifted[k] - base[k]) / step;
+                    assert!((numeric - sensitivity[[i * 3 + j, k]]).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_antithetic_ensemble_reduces_variance() {
+        let matrix = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.9, 0.05, 0.05,
+                0.3, 0.6, 0.1,
+                0.5, 0.2, 0.3,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+
+        // Spread of the day-1 Sunny estimate across seeds, with 20 runs per estimate
+        let variance = |estimates: Vec<f64>| {
+            let mean = estimates.iter().sum::<f64>() / estimates.len() as f64;
+            estimates.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / estimates.len() as f64
+        };
+        let plain = variance((0..200u64).map(|seed| {
+            let paths = simulate_ensemble(&matrix, StateType::Sunny, 2, 20, seed);
+            ensemble_distribution(&matrix, &paths).unwrap()[1][0]
+        }).collect());
+        let antithetic = variance((0..200u64).map(|seed| {
+            simulate_ensemble_antithetic(&matrix, StateType::Sunny, 2, 10, seed)[1][0]
+        }).collect());
+
+        assert!(antithetic < plain);
+    }
+
+    #[test]
+    fn test_generate_report() {
+        let json = sample_weather_json();
+        let options = ParseOptions::default();
+        let report = generate_report(&json, 5, 7, &options).unwrap();
+        assert_eq!(report.location, "London");
+        assert_eq!(report.num_observations, 7);
+        assert_eq!(report.forecast.len(), 5);
+        // Forecasts start from the last observed day (Cloudy) and are reproducible
+        assert_eq!(report.forecast[0].state, "Cloudy");
+        let again = generate_report(&json, 5, 7, &options).unwrap();
+        let states: Vec<&str> = report.forecast.iter().map(|d| d.state.as_str()).collect();
+        let again_states: Vec<&str> = again.forecast.iter().map(|d| d.state.as_str()).collect();
+        assert_eq!(states, again_states);
+        assert!(serde_json::to_string(&report).is_ok());
+
+        assert!(matches!(generate_report(&json, 0, 7, &options), Err(EngineError::InvalidInput(_))));
+        assert!(matches!(generate_report("{}", 5, 7, &options), Err(EngineError::Parse(_))));
+
+        // An unmatched last condition falls back to the caller's default state
+        let unmatched = json.replace("\"Cloudy\"", "\"Volcanic ash\"");
+        let sunny_default = ParseOptions { default_state: Some(StateType::Sunny), ..Default::default() };
+        assert_eq!(generate_report(&unmatched, 5, 7, &options).unwrap().forecast[0].state, "Cloudy");
+        assert_eq!(generate_report(&unmatched, 5, 7, &sunny_default).unwrap().forecast[0].state, "Sunny");
+    }
+
+    #[test]
+    fn test_brier_score() {
+        let matrix = sample_matrix();
+        let forecast = forecast_distribution(&matrix, StateType::Sunny, 2).unwrap();
+        // Day 1 from Sunny is [0.6, 0.1, 0.3]; a Sunny outcome scores 0.16 + 0.01 + 0.09
+        let score = brier_score(&forecast, &matrix.states, &[StateType::Sunny, StateType::Sunny]).unwrap();
+        assert!((score - 0.26).abs() < 1e-12);
+
+        let perfect = vec![vec![1.0, 0.0, 0.0], vec![0.0, 0.0, 1.0]];
+        assert_eq!(brier_score(&perfect, &matrix.states, &[StateType::Sunny, StateType::Cloudy]).unwrap(), 0.0);
+        assert!(brier_score(&forecast, &matrix.states, &[StateType::Sunny]).is_err());
+        assert!(brier_score(&forecast, &[StateType::Sunny, StateType::Rainy], &[StateType::Sunny; 2]).is_err());
+
+        // Rain two steps from the Sunny outcome doubles its term: 0.16 + 0.01 * 2 + 0.09
+        assert_eq!(state_distance(StateType::Sunny, StateType::Rainy), 2.0);
+        assert_eq!(state_distance(StateType::Cloudy, StateType::Rainy), 1.0);
+        let weighted = brier_score_weighted(&forecast, &matrix.states, &[StateType::Sunny, StateType::Sunny], state_distance).unwrap();
+        assert!((weighted - 0.27).abs() < 1e-12);
+
+        // A forecast from a reordered matrix scores the same once its order is passed along
+        let reordered = TransitionMatrix::from_rows(
+            vec![vec![0.5, 0.2, 0.3], vec![0.1, 0.6, 0.3], vec![0.2, 0.3, 0.5]],
+            &["Rainy".to_string(), "Sunny".to_string(), "Cloudy".to_string()],
+        ).unwrap();
+        let reordered_forecast = forecast_distribution(&reordered, StateType::Sunny, 2).unwrap();
+        let reordered_score = brier_score_weighted(&reordered_forecast, &reordered.states, &[StateType::Sunny; 2], state_distance).unwrap();
+        assert!((reordered_score - weighted).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_detect_regime_change() {
+        // Twenty alternating Sunny/Rainy days followed by twenty Sunny days
+        let mut data = HistoricalData::new("Test".to_string());
+        for i in 0..40 {
+            let state = if i < 20 && i % 2 == 1 { StateType::Rainy } else { StateType::Sunny };
+            data.add_state(WeatherState::new(state, i * 86400));
+        }
+        assert_eq!(detect_regime_change(&data, 5), Some(20));
+
+        // A single regime throughout has no change point
+        let mut steady = HistoricalData::new("Test".to_string());
+        for i in 0..40 {
+            let state = if i % 2 == 1 { StateType::Rainy } else { StateType::Sunny };
+            steady.add_state(WeatherState::new(state, i * 86400));
+        }
+        assert_eq!(detect_regime_change(&steady, 5), None);
+        assert_eq!(detect_regime_change(&steady, 30), None);
+    }
+
+    #[test]
+    fn test_resample_hourly_to_daily() {
+        let mut data = HistoricalData::new("Test".to_string());
+        let hourly = [
+            (0, StateType::Sunny), (1, StateType::Rainy), (2, StateType::Sunny),
+            (24, StateType::Rainy), (25, StateType::Cloudy),
+            // Day 2 has no observations
+            (72, StateType::Cloudy),
+        ];
+        for (hour, state) in hourly {
+            data.add_state(WeatherState::new(state, hour * 3600));
+        }
+
+        let mode = data.resample(86400, ResampleStrategy::Mode);
+        let states: Vec<StateType> = mode.iter().map(|w| w.state).collect();
+        assert_eq!(states, vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy]);
+        let timestamps: Vec<i64> = mode.iter().map(|w| w.timestamp).collect();
+        assert_eq!(timestamps, vec![0, 86400, 3 * 86400]);
+
+        let last = data.resample(86400, ResampleStrategy::Last);
+        assert_eq!(last.states[1].state, StateType::Cloudy);
+    }
+
+    #[test]
+    fn test_steady_state_nonuniformity() {
+        let uniform = TransitionMatrix {
+            matrix: Array2::from_elem((3, 3), 1.0 / 3.0),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        assert!(steady_state_nonuniformity(&uniform).abs() < 1e-12);
+
+        let dominated = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                1.0, 0.0, 0.0,
+                1.0, 0.0, 0.0,
+                1.0, 0.0, 0.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        assert!((steady_state_nonuniformity(&dominated) - 3f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_expected_max_streak() {
+        let matrix = sample_matrix();
+        let estimate = expected_max_streak(&matrix, StateType::Sunny, StateType::Sunny, 30, 500, 11);
+        assert!(estimate.mean >= 1.0 && estimate.mean <= 30.0);
+        assert!(estimate.percentile_95 as f64 >= estimate.mean);
+
+        // A state that always persists runs for the whole horizon
+        let identity = TransitionMatrix::identity(matrix.states.clone());
+        let estimate = expected_max_streak(&identity, StateType::Rainy, StateType::Rainy, 10, 20, 1);
+        assert_eq!(estimate, MaxStreakEstimate { mean: 10.0, percentile_95: 10 });
+    }
+
+    #[test]
+    fn test_matrix_equality_and_hash() {
+        use std::collections::HashSet;
+
+        let matrix = sample_matrix();
+        let mut noisy = matrix.clone();
+        noisy.matrix[[0, 0]] += 1e-12;
+        noisy.matrix[[0, 1]] -= 1e-12;
+        assert_eq!(matrix, noisy);
+
+        let mut reordered = matrix.clone();
+        reordered.states.swap(0, 1);
+        assert_ne!(matrix, reordered);
+
+        let unique: HashSet<TransitionMatrix> = [matrix.clone(), noisy, TransitionMatrix::new()].into_iter().collect();
+        assert_eq!(unique.len(), 2);
+    }
+
+    #[test]
+    fn test_next_state_distribution() {
+        let matrix = sample_matrix();
+        assert_eq!(next_state_distribution(&matrix, StateType::Rainy).unwrap(), vec![0.2, 0.5, 0.3]);
+        assert_eq!(next_state_distribution(&matrix, StateType::Rainy).unwrap(), forecast_distribution(&matrix, StateType::Rainy, 2).unwrap()[1]);
+    }
+
+    #[test]
+    fn test_simulate_capped_limits_streaks() {
+        // Sunny almost never leaves on its own
+        let matrix = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.98, 0.01, 0.01,
+                0.5, 0.5, 0.0,
+                0.0, 0.0, 1.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let results = simulate_capped(&matrix, StateType::Sunny, 200, 3, &mut SeededRandom::new(5)).unwrap();
+        assert_eq!(results.len(), 200);
+
+        let mut streak = 0;
+        let mut previous = None;
+        for weather_state in &results {
+            streak = if previous == Some(weather_state.state) { streak + 1 } else { 1 };
+            assert!(streak <= 3);
+            previous = Some(weather_state.state);
+        }
+
+        assert!(simulate_capped(&matrix, StateType::Sunny, 5, 0, &mut SeededRandom::new(5)).is_err());
+    }
+
+    #[test]
+    fn test_entropy_trajectory() {
+        let matrix = sample_matrix();
+        let trajectory = entropy_trajectory(&matrix, StateType::Sunny, 60).unwrap();
+        assert_eq!(trajectory.len(), 60);
+        assert_eq!(trajectory[0], 0.0);
+        assert!(trajectory[1] > trajectory[0]);
+
+        let steady_entropy = distribution_entropy(&steady_state_exact(&matrix).unwrap());
+        assert!((trajectory[59] - steady_entropy).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_non_standard_layout_is_handled() {
+        // Same probabilities as sample_matrix, stored column-major
+        let standard = sample_matrix();
+        let transposed = standard.matrix.t().as_standard_layout().into_owned();
+        let column_major = TransitionMatrix {
+            matrix: transposed.reversed_axes(),
+            states: standard.states.clone(),
+            counts: None,
+        };
+        assert!(column_major.matrix.as_slice().is_none());
+
+        let data = MatrixData::from(&column_major);
+        assert_eq!(data.matrix, MatrixData::from(&standard).matrix);
+
+        let seeded = simulate_weather_with_rng(&column_major, StateType::Sunny, 20, &mut SeededRandom::new(3));
+        let expected = simulate_weather_with_rng(&standard, StateType::Sunny, 20, &mut SeededRandom::new(3));
+        assert!(seeded.iter().zip(expected.iter()).all(|(a, b)| a.state == b.state));
+        assert_eq!(mixing_time(&column_major, StateType::Sunny, 1e-6), mixing_time(&standard, StateType::Sunny, 1e-6));
+        assert_eq!(similar_state_pairs(&column_major, 1.0).len(), 3);
+    }
+
+    #[test]
+    fn test_surprise() {
+        let matrix = sample_matrix();
+        // P[Rainy, Rainy] = 0.5 is exactly one bit of surprise
+        assert!((surprise(&matrix, StateType::Rainy, StateType::Rainy).unwrap() - 1.0).abs() < 1e-12);
+        assert!(surprise(&matrix, StateType::Sunny, StateType::Rainy).unwrap() > surprise(&matrix, StateType::Sunny, StateType::Sunny).unwrap());
+
+        let identity = TransitionMatrix::identity(matrix.states.clone());
+        assert_eq!(surprise(&identity, StateType::Sunny, StateType::Rainy).unwrap(), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_repair_rows() {
+        let mut matrix = sample_matrix();
+        assert!(!repair_rows(&mut matrix));
+
+        matrix.matrix[[1, 1]] += 0.01;
+        assert!(repair_rows(&mut matrix));
+        assert!(matrix.is_stochastic());
+        assert_eq!(DataWarning::RepairedRows.to_string(), "Transition rows did not sum to 1 and were re-normalized");
+    }
+
+    #[test]
+    fn test_posterior_sample() {
+        let data = parse_weather_data(&sample_weather_json()).unwrap();
+        let sample = posterior_sample(&data, 1.0, 42);
+        assert!(sample.is_stochastic());
+        assert!(sample.matrix.iter().all(|&p| p > 0.0));
+        assert_eq!(sample, posterior_sample(&data, 1.0, 42));
+
+        // The mean of many posterior draws approaches (counts + alpha) / (row total + 3 * alpha)
+        let counts = transition_counts(&data);
+        let draws = 2000;
+        let mean_sunny_stay = (0..draws)
+            .map(|seed| posterior_sample(&data, 1.0, seed).matrix[[0, 0]])
+            .sum::<f64>() / draws as f64;
+        let expected = (counts[[0, 0]] + 1.0) / (counts.row(0).sum() + 3.0);
+        assert!((mean_sunny_stay - expected).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_limiting_distributions() {
+        // Sunny and Rainy are absorbing; Cloudy drains into them 2:1
+        let reducible = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                1.0, 0.0, 0.0,
+                0.0, 1.0, 0.0,
+                0.4, 0.2, 0.4,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let limits = limiting_distributions(&reducible);
+        assert_eq!(limits[0], vec![1.0, 0.0, 0.0]);
+        assert_eq!(limits[1], vec![0.0, 1.0, 0.0]);
+        assert!((limits[2][0] - 2.0 / 3.0).abs() < 1e-9);
+        assert!((limits[2][1] - 1.0 / 3.0).abs() < 1e-9);
+
+        // A Sunny/Rainy cycle averages out instead of oscillating
+        let periodic = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.0, 1.0, 0.0,
+                1.0, 0.0, 0.0,
+                0.0, 0.0, 1.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        assert_eq!(limiting_distributions(&periodic)[0], vec![0.5, 0.5, 0.0]);
+
+        let limits = limiting_distributions(&sample_matrix());
+        let steady = steady_state_exact(&sample_matrix()).unwrap();
+        assert!(limits.iter().all(|row| row.iter().zip(steady.iter()).all(|(a, b)| (a - b).abs() < 1e-9)));
+    }
+
+    #[test]
+    fn test_transition_support() {
+        let data = parse_weather_data(&sample_weather_json()).unwrap();
+        let matrix = build_transition_matrix(&data);
+        // Sunny -> Sunny happens once (June 1 -> 2); Sunny -> Rainy twice
+        assert_eq!(transition_support(&matrix, StateType::Sunny, StateType::Sunny), Some(1.0));
+        assert_eq!(transition_support(&matrix, StateType::Sunny, StateType::Rainy), Some(2.0));
+        assert_eq!(transition_support(&matrix, StateType::Rainy, StateType::Rainy), Some(0.0));
+        assert_eq!(transition_support(&sample_matrix(), StateType::Sunny, StateType::Sunny), None);
+
+        // Half-trusted June 1 contributes half of its Sunny -> Sunny transition
+        let mut weighted_data = data.clone();
+        weighted_data.states[0].weight = 0.5;
+        let weighted = build_transition_matrix_reliability(&weighted_data);
+        assert_eq!(transition_support(&weighted, StateType::Sunny, StateType::Sunny), Some(0.5));
+
+        // Counts survive a JSON round trip
+        let restored = TransitionMatrix::from_json(&serde_json::to_string(&matrix).unwrap()).unwrap();
+        assert_eq!(restored.counts, matrix.counts);
+    }
+
+    #[test]
+    fn test_expected_first_occurrence_date() {
+        let matrix = sample_matrix();
+        let start = 1_717_200_000;
+        let days = mean_first_passage_time(&matrix, StateType::Sunny, StateType::Rainy).unwrap();
+        let expected = start + (days * 86400.0).round() as i64;
+        assert_eq!(expected_first_occurrence_date(&matrix, StateType::Sunny, StateType::Rainy, start), Ok(expected));
+        assert_eq!(expected_first_occurrence_date(&matrix, StateType::Rainy, StateType::Rainy, start), Ok(start));
+
+        let identity = TransitionMatrix::identity(matrix.states.clone());
+        assert!(expected_first_occurrence_date(&identity, StateType::Sunny, StateType::Rainy, start).is_err());
+    }
+
+    #[test]
+    fn test_flat_soft_observations() {
+        let json = r#"[
+            {"date": "2024-06-01", "condition": "Sunny"},
+            {"date": "2024-06-02", "probs": {"sunny": 0.2, "rainy": 0.7, "cloudy": 0.1}},
+            {"date": "2024-06-03", "condition": "Overcast"}
+        ]"#;
+        let observations = parse_flat_observations(json).unwrap();
+        assert_eq!(observations[0].probabilities, vec![1.0, 0.0, 0.0]);
+        assert_eq!(observations[2].probabilities, vec![0.0, 0.0, 1.0]);
+
+        let matrix = build_fuzzy_transition_matrix(&observations);
+        assert!(matrix.is_stochastic());
+        // Sunny row: 0.7 of a transition to Rainy, out of 1.2 total (0.2 more from the soft day)
+        assert!((matrix.matrix[[0, 1]] - 0.7 / 1.2).abs() < 1e-12);
+
+        // Hard observations reproduce the ordinary builder
+        let data = parse_weather_data(&sample_weather_json()).unwrap();
+        let hard: Vec<SoftObservation> = data.iter().map(|w| SoftObservation {
+            timestamp: w.timestamp,
+            probabilities: [StateType::Sunny, StateType::Rainy, StateType::Cloudy].iter()
+                .map(|&s| if s == w.state { 1.0 } else { 0.0 })
+                .collect(),
+        }).collect();
+        assert_eq!(build_fuzzy_transition_matrix(&hard), build_transition_matrix(&data));
+
+        let bad = r#"[{"date": "2024-06-01", "probs": {"sunny": -1, "rainy": 0, "cloudy": 0}}, {"date": "2024-06-02", "condition": "Sunny"}]"#;
+        assert!(parse_flat_observations(bad).is_err());
+    }
+
+    #[test]
+    fn test_condition_number() {
+        let matrix = sample_matrix();
+        let condition = condition_number(&matrix);
+        assert!(condition.is_finite() && condition >= 1.0);
+        assert!(statistics_warnings(&matrix).is_empty());
+
+        // Two absorbing states leave the steady state undetermined
+        let identity = TransitionMatrix::identity(matrix.states.clone());
+        assert_eq!(condition_number(&identity), f64::INFINITY);
+        assert_eq!(statistics_warnings(&identity).len(), 1);
+    }
+
+    #[test]
+    fn test_live_forecaster_window() {
+        let mut forecaster = LiveForecaster::new(3);
+        forecaster.observe(StateType::Rainy, 0);
+        forecaster.observe(StateType::Rainy, 86400);
+        assert_eq!(forecaster.observe(StateType::Rainy, 2 * 86400), vec![0.0, 1.0, 0.0]);
+
+        // Once the old rainy days are evicted only the sunny run remains
+        forecaster.observe(StateType::Sunny, 3 * 86400);
+        forecaster.observe(StateType::Sunny, 4 * 86400);
+        let tomorrow = forecaster.observe(StateType::Sunny, 5 * 86400);
+        assert_eq!(tomorrow, vec![1.0, 0.0, 0.0]);
+        assert_eq!(forecaster.window().count(), 3);
+        assert_eq!(forecaster.window().next().unwrap().timestamp, 3 * 86400);
+    }
+
+    #[test]
+    fn test_kemeny_constant_matches_first_passage_times() {
+        let matrix = sample_matrix();
+        let kemeny = kemeny_constant(&matrix);
+        let pi = steady_state_exact(&matrix).unwrap();
+
+        // The same weighted passage time from every starting state
+        for &from in &matrix.states {
+            let weighted: f64 = matrix.states.iter().zip(pi.iter())
+                .filter(|&(&to, _)| to != from)
+                .map(|(&to, &p)| p * mean_first_passage_time(&matrix, from, to).unwrap())
+                .sum();
+            assert!((weighted - kemeny).abs() < 1e-9);
+        }
+
+        let identity = TransitionMatrix::identity(matrix.states.clone());
+        assert_eq!(kemeny_constant(&identity), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_build_transition_matrix_reliability() {
+        let mut data = parse_weather_data(&sample_weather_json()).unwrap();
+        let plain = build_transition_matrix(&data);
+        assert_eq!(build_transition_matrix_reliability(&data), plain);
+
+        // Distrusting one source day shrinks only its row's contribution
+        let from = data.states[0].state;
+        data.states[0].weight = 0.0;
+        let weighted = build_transition_matrix_reliability(&data);
+        assert!(weighted.is_stochastic());
+        let row = weighted.state_index(from).unwrap();
+        let counts = weighted.counts.as_ref().unwrap();
+        assert_eq!(counts.row(row).sum(), plain.counts.as_ref().unwrap().row(row).sum() - 1.0);
+    }
+
+    #[test]
+    fn test_to_mermaid() {
+        let matrix = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.9, 0.1, 0.0,
+                0.5, 0.25, 0.25,
+                0.0, 0.0, 1.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let diagram = to_mermaid(&matrix, 0.2);
+
+        assert!(diagram.starts_with("stateDiagram-v2\n"));
+        assert!(diagram.contains("    Sunny --> Sunny: 0.90\n"));
+        assert!(diagram.contains("    Rainy --> Cloudy: 0.25\n"));
+        assert!(diagram.contains("    Cloudy --> Cloudy: 1.00\n"));
+        assert!(!diagram.contains("Sunny --> Rainy"));
+        assert_eq!(diagram.matches("-->").count(), 5);
+    }
+
+    #[test]
+    fn test_build_transition_matrix_shrinkage() {
+        let data = parse_weather_data(&sample_weather_json()).unwrap();
+        let plain = build_transition_matrix(&data);
+        assert_eq!(build_transition_matrix_shrinkage(&data, 0.0), plain);
+
+        let counts = plain.counts.clone().unwrap();
+        let shrunk = build_transition_matrix_shrinkage(&data, 2.0);
+        assert!(shrunk.is_stochastic());
+
+        let marginal: Vec<f64> = plain.states.iter()
+            .map(|&state| data.iter().filter(|s| s.state == state).count() as f64 / data.len() as f64)
+            .collect();
+        for (i, count_row) in counts.rows().into_iter().enumerate() {
+            let shrink = 2.0 / (2.0 + count_row.sum());
+            for (j, &prior) in marginal.iter().enumerate() {
+                let expected = (1.0 - shrink) * plain.matrix[[i, j]] + shrink * prior;
+                assert!((shrunk.matrix[[i, j]] - expected).abs() < 1e-12);
+            }
+        }
+
+        // Overwhelming strength collapses every row onto the marginal
+        let flat = build_transition_matrix_shrinkage(&data, 1e12);
+        for row in flat.matrix.rows() {
+            for (p, prior) in row.iter().zip(marginal.iter()) {
+                assert!((p - prior).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reversibility_defect() {
+        let birth_death = TransitionMatrix::from_rows(
+            vec![vec![0.7, 0.3, 0.0], vec![0.2, 0.5, 0.3], vec![0.0, 0.4, 0.6]],
+            &["Sunny".to_string(), "Cloudy".to_string(), "Rainy".to_string()],
+        ).unwrap();
+        assert!(reversibility_defect(&birth_death) < 1e-9);
+        assert!(is_reversible(&birth_death));
+
+        // A pure cycle sends all flow one way: pi = 1/3 each, so the defect is 1/3
+        let cycle = TransitionMatrix::from_rows(
+            vec![vec![0.0, 1.0, 0.0], vec![0.0, 0.0, 1.0], vec![1.0, 0.0, 0.0]],
+            &["Sunny".to_string(), "Rainy".to_string(), "Cloudy".to_string()],
+        ).unwrap();
+        assert!((reversibility_defect(&cycle) - 1.0 / 3.0).abs() < 1e-9);
+        assert!(!is_reversible(&cycle));
+    }
+
+    #[test]
+    fn test_simulate_and_summarize() {
+        let matrix = sample_matrix();
+        let summary = simulate_and_summarize(&matrix, StateType::Rainy, 30, 11).unwrap();
+        assert_eq!(summary.simulation.len(), 30);
+        assert_eq!(summary.simulation[0].state, "Rainy");
+
+        // Statistics describe exactly the returned run
+        let sunny_days = summary.simulation.iter().filter(|d| d.state == "Sunny").count();
+        assert!((summary.distribution.sunny - sunny_days as f64 / 30.0).abs() < 1e-12);
+        let total = summary.distribution.sunny + summary.distribution.rainy + summary.distribution.cloudy;
+        assert!((total - 1.0).abs() < 1e-12);
+
+        let again = simulate_and_summarize(&matrix, StateType::Rainy, 30, 11).unwrap();
+        assert!(summary.simulation.iter().zip(again.simulation.iter()).all(|(a, b)| a.state == b.state));
+        assert!(matches!(simulate_and_summarize(&matrix, StateType::Rainy, 0, 11), Err(EngineError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_pattern_probability() {
+        let matrix = sample_matrix();
+        let sunny = matrix.state_index(StateType::Sunny).unwrap();
+        let p = |from: StateType, to: StateType| {
+            matrix.matrix[[matrix.state_index(from).unwrap(), matrix.state_index(to).unwrap()]]
+        };
+
+        // No constraints always matches
+        assert!((pattern_probability(&matrix, StateType::Rainy, &[None, None, None]).unwrap() - 1.0).abs() < 1e-12);
+
+        // A single constrained day matches the forecast marginal
+        let marginal = forecast_distribution(&matrix, StateType::Rainy, 4).unwrap()[3][sunny];
+        let masked = pattern_probability(&matrix, StateType::Rainy, &[None, None, None, Some(StateType::Sunny)]).unwrap();
+        assert!((masked - marginal).abs() < 1e-12);
+
+        // Fully specified patterns are the product of transitions
+        let full = [Some(StateType::Rainy), Some(StateType::Cloudy), Some(StateType::Sunny)];
+        let expected = p(StateType::Rainy, StateType::Cloudy) * p(StateType::Cloudy, StateType::Sunny);
+        assert!((pattern_probability(&matrix, StateType::Rainy, &full).unwrap() - expected).abs() < 1e-12);
+
+        // Day 0 must agree with the initial state
+        assert_eq!(pattern_probability(&matrix, StateType::Rainy, &[Some(StateType::Sunny)]).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_is_stochastic_tol() {
+        let mut matrix = sample_matrix();
+        assert!(matrix.is_stochastic_tol(1e-12));
+
+        // f32 round-off passes a loose check but not the default one
+        matrix.matrix[[0, 0]] += 5e-5;
+        assert!(matrix.is_stochastic_tol(1e-4));
+        assert!(!matrix.is_stochastic());
+        assert!(!matrix.is_stochastic_tol(1e-12));
+    }
+
+    #[test]
+    fn test_stationary_eigenvector() {
+        let matrix = sample_matrix();
+        let eigen = stationary_eigenvector(&matrix).unwrap();
+        let exact = steady_state_exact(&matrix).unwrap();
+        let power = calculate_steady_state(&matrix);
+        for ((e, x), p) in eigen.iter().zip(exact.iter()).zip(power.iter()) {
+            assert!((e - x).abs() < 1e-12);
+            assert!((e - p).abs() < 1e-6);
+        }
+
+        let two_state = TransitionMatrix {
+            matrix: Array2::from_shape_vec((2, 2), vec![0.9, 0.1, 0.3, 0.7]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy],
+            counts: None,
+        };
+        let eigen = stationary_eigenvector(&two_state).unwrap();
+        assert!((eigen[0] - 0.75).abs() < 1e-12);
+
+        let identity = TransitionMatrix::identity(matrix.states.clone());
+        assert!(stationary_eigenvector(&identity).is_err());
+    }
+
+    #[test]
+    fn test_order_disagreement() {
+        let history = |pattern: &[StateType]| {
+            let mut data = HistoricalData::new("Test".to_string());
+            for (day, &state) in pattern.iter().cycle().take(60).enumerate() {
+                data.add_state(WeatherState::new(state, day as i64 * 86400));
+            }
+            data
+        };
+        let (s, r, c) = (StateType::Sunny, StateType::Rainy, StateType::Cloudy);
+
+        // A plain cycle is fully first-order, so the extra order adds nothing
+        let cycle = history(&[s, r, c]);
+        assert!(order_disagreement(&cycle, &[s, r], 5).unwrap() < 1e-12);
+
+        // Two sunny days then rain: after (Sunny, Sunny) rain is certain, which the
+        // first-order model only gives even odds
+        let pattern = history(&[s, s, r]);
+        let model = SecondOrderModel::train(&pattern);
+        assert_eq!(model.forecast_distribution(s, s, 2)[1], vec![0.0, 1.0, 0.0]);
+        let disagreement = order_disagreement(&pattern, &[s, s], 1).unwrap();
+        assert!((disagreement - 0.5).abs() < 1e-9);
+
+        assert!(order_disagreement(&pattern, &[s], 3).is_err());
+        assert!(order_disagreement(&pattern, &[s, s], 0).is_err());
+    }
+
+    #[test]
+    fn test_effective_sample_size() {
+        // Long sunny and rainy spells are strongly autocorrelated
+        let mut persistent = HistoricalData::new("Test".to_string());
+        for day in 0..200 {
+            let state = if (day / 20) % 2 == 0 { StateType::Sunny } else { StateType::Rainy };
+            persistent.add_state(WeatherState::new(state, day as i64 * 86400));
+        }
+        let ess = effective_sample_size(&persistent);
+        assert!(ess < 20.0, "ess = {}", ess);
+        assert!(recommended_sample_size_autocorrelated(0.05, 0.95, &persistent) > 10 * 385);
+
+        // Too short to estimate autocorrelation: every day counts
+        let data = parse_weather_data(&sample_weather_json()).unwrap();
+        let short = HistoricalData { states: data.states[..2].to_vec(), ..data };
+        assert_eq!(effective_sample_size(&short), 2.0);
+
+        // Perfect alternation has rho = -1, which must not inflate the count past N
+        let mut alternating = HistoricalData::new("Test".to_string());
+        for day in 0..100 {
+            let state = if day % 2 == 0 { StateType::Sunny } else { StateType::Rainy };
+            alternating.add_state(WeatherState::new(state, day as i64 * 86400));
+        }
+        assert!(autocorrelation(&alternating, 1)[0] < -0.9);
+        assert_eq!(effective_sample_size(&alternating), 100.0);
+        assert_eq!(
+            recommended_sample_size_autocorrelated(0.05, 0.95, &alternating),
+            recommended_sample_size(0.05, 0.95),
+        );
+    }
+
+    #[test]
+    fn test_build_transition_matrix_range() {
+        let data = parse_weather_data(&sample_weather_json()).unwrap();
+        let first = data.states.first().unwrap().timestamp;
+        let last = data.states.last().unwrap().timestamp;
+        assert_eq!(build_transition_matrix_range(&data, first, last + 1), build_transition_matrix(&data));
+
+        // Only the first day is a source, but its transition into day 1 counts
+        let one_source = build_transition_matrix_range(&data, first, first + 1);
+        let counts = one_source.counts.as_ref().unwrap();
+        assert_eq!(counts.sum(), 1.0);
+        let i = one_source.state_index(data.states[0].state).unwrap();
+        let j = one_source.state_index(data.states[1].state).unwrap();
+        assert_eq!(counts[[i, j]], 1.0);
+
+        let empty = build_transition_matrix_range(&data, last + 1, last + 2);
+        assert_eq!(empty.counts.as_ref().unwrap().sum(), 0.0);
+        assert!(empty.is_stochastic());
+    }
+
+    #[test]
+    fn test_simulate_from_steady_state() {
+        let matrix = sample_matrix();
+        let run = simulate_from_steady_state(&matrix, 10, 5);
+        assert_eq!(run.len(), 10);
+        let again = simulate_from_steady_state(&matrix, 10, 5);
+        assert!(run.iter().zip(again.iter()).all(|(a, b)| a.state == b.state));
+
+        // Day-0 states follow the steady state across seeds
+        let steady_state = steady_state_auto(&matrix).distribution;
+        let sunny = matrix.state_index(StateType::Sunny).unwrap();
+        let starts = (0..4000u64)
+            .filter(|&seed| simulate_from_steady_state(&matrix, 1, seed)[0].state == StateType::Sunny)
+            .count();
+        assert!((starts as f64 / 4000.0 - steady_state[sunny]).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_best_day_for() {
+        // Alternating weather: Rainy is certain on odd days, so day 1 wins the tie
+        let alternating = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.0, 1.0, 0.0,
+                1.0, 0.0, 0.0,
+                0.0, 0.0, 1.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        assert_eq!(best_day_for(&alternating, StateType::Sunny, StateType::Rainy, 5), Ok((1, 1.0)));
+        assert_eq!(best_day_for(&alternating, StateType::Sunny, StateType::Sunny, 5), Ok((2, 1.0)));
+        assert_eq!(best_day_for(&alternating, StateType::Sunny, StateType::Cloudy, 5), Ok((1, 0.0)));
+        assert!(best_day_for(&alternating, StateType::Sunny, StateType::Rainy, 0).is_err());
+
+        let matrix = sample_matrix();
+        let (day, probability) = best_day_for(&matrix, StateType::Rainy, StateType::Sunny, 10).unwrap();
+        let sunny = matrix.state_index(StateType::Sunny).unwrap();
+        let forecast = forecast_distribution(&matrix, StateType::Rainy, 11).unwrap();
+        assert_eq!(probability, forecast[day][sunny]);
+        assert!(forecast[1..].iter().all(|dist| dist[sunny] <= probability));
+    }
+
+    #[test]
+    fn test_duplicate_state_labels_rejected() {
+        let rows = vec![vec![0.5, 0.5, 0.0], vec![0.2, 0.8, 0.0], vec![0.0, 0.0, 1.0]];
+        let names = ["Sunny".to_string(), "Rainy".to_string(), "sunny".to_string()];
+        match TransitionMatrix::from_rows(rows, &names) {
+            Err(ParseError::InvalidData(msg)) => assert!(msg.contains("Duplicate state label Sunny")),
+            other => panic!("expected InvalidData, got {:?}", other),
+        }
+
+        let mut json = serde_json::to_string(&sample_matrix()).unwrap();
+        json = json.replacen("\"Cloudy\"", "\"Rainy\"", 1);
+        assert!(TransitionMatrix::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn test_backtest() {
+        // A strict cycle becomes perfectly predictable once each transition has been seen
+        let mut data = HistoricalData::new("Test".to_string());
+        let cycle = [StateType::Sunny, StateType::Rainy, StateType::Cloudy];
+        for (day, &state) in cycle.iter().cycle().take(30).enumerate() {
+            data.add_state(WeatherState::new(state, day as i64 * 86400));
+        }
+        let result = backtest(&data, 4);
+        assert_eq!(result.predictions, 26);
+        assert_eq!(result.accuracy, 1.0);
+        assert_eq!(result.per_state_accuracy.rainy, 1.0);
+        assert!(result.mean_log_loss < 1e-12);
+
+        // From the start, the first guesses come from empty rows and mostly miss
+        let early = backtest(&data, 0);
+        assert_eq!(early.predictions, 29);
+        assert!(early.accuracy < 1.0);
+        assert!(early.mean_log_loss > 0.0);
+
+        assert_eq!(backtest(&data, 30).predictions, 0);
+    }
+
+    #[test]
+    fn test_simulate_until_absorbed() {
+        // Rainy leads to Cloudy, which never clears
+        let matrix = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.5, 0.5, 0.0,
+                0.0, 0.0, 1.0,
+                0.0, 0.0, 1.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let outcome = simulate_until_absorbed(&matrix, StateType::Rainy, 365, 3, &mut SeededRandom::new(1)).unwrap();
+        assert_eq!(outcome.absorbed_at, Some(1));
+        assert_eq!(outcome.results.len(), 4);
+        assert!(outcome.results[1..].iter().all(|w| w.state == StateType::Cloudy));
+
+        // Non-absorbing chains run to completion
+        let outcome = simulate_until_absorbed(&sample_matrix(), StateType::Rainy, 100, 3, &mut SeededRandom::new(1)).unwrap();
+        assert_eq!(outcome.absorbed_at, None);
+        assert_eq!(outcome.results.len(), 100);
+
+        assert!(simulate_until_absorbed(&matrix, StateType::Rainy, 10, 0, &mut SeededRandom::new(1)).is_err());
+    }
+
+    #[test]
+    fn test_forecast_days() {
+        let matrix = sample_matrix();
+        let days = forecast_days(&matrix, StateType::Rainy, 3, 1_717_200_000).unwrap();
+        let raw = forecast_distribution(&matrix, StateType::Rainy, 3).unwrap();
+        let entropies = entropy_trajectory(&matrix, StateType::Rainy, 3).unwrap();
+
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[0].distribution.rainy, 1.0);
+        assert_eq!(days[0].entropy, 0.0);
+        for (i, day) in days.iter().enumerate() {
+            assert_eq!(day.day, i);
+            assert_eq!(day.timestamp, 1_717_200_000 + i as i64 * 86400);
+            assert_eq!(day.distribution.to_states(&matrix.states), raw[i]);
+            assert_eq!(day.entropy, entropies[i]);
+        }
+    }
+
+    #[test]
+    fn test_improvement_and_deterioration_probability() {
+        let matrix = sample_matrix();
+        let p = |from: StateType, to: StateType| {
+            matrix.matrix[[matrix.state_index(from).unwrap(), matrix.state_index(to).unwrap()]]
+        };
+
+        let improve = improvement_probability(&matrix, StateType::Cloudy).unwrap();
+        let worsen = deterioration_probability(&matrix, StateType::Cloudy).unwrap();
+        assert_eq!(improve, p(StateType::Cloudy, StateType::Sunny));
+        assert_eq!(worsen, p(StateType::Cloudy, StateType::Rainy));
+
+        let from_rain = improvement_probability(&matrix, StateType::Rainy).unwrap();
+        assert!((from_rain - (1.0 - p(StateType::Rainy, StateType::Rainy))).abs() < 1e-12);
+        assert_eq!(improvement_probability(&matrix, StateType::Sunny).unwrap(), 0.0);
+        assert_eq!(deterioration_probability(&matrix, StateType::Rainy).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_simulation_window() {
+        let results = simulate_weather_with_rng(&sample_matrix(), StateType::Sunny, 90, &mut SeededRandom::new(4));
+        let window = simulation_window(&results, 30, 60);
+        assert_eq!(window.len(), 30);
+        assert_eq!(window[0].timestamp, results[30].timestamp);
+
+        // Statistics only see the window
+        let expected = calculate_state_distribution(&results[30..60]);
+        assert_eq!(calculate_state_distribution(window), expected);
+
+        assert_eq!(simulation_window(&results, 80, usize::MAX).len(), 10);
+        assert!(simulation_window(&results, 60, 30).is_empty());
+        assert!(simulation_window(&results, 200, 300).is_empty());
+    }
+
+    #[test]
+    fn test_ensemble_medoid() {
+        let path = |states: &[StateType]| -> Vec<WeatherState> {
+            states.iter().enumerate().map(|(day, &s)| WeatherState::new(s, day as i64 * 86400)).collect()
+        };
+        let (s, r, c) = (StateType::Sunny, StateType::Rainy, StateType::Cloudy);
+
+        // The all-cloudy path sits between the sunny and rainy extremes
+        let paths = vec![path(&[s, s, s]), path(&[r, r, r]), path(&[c, c, c]), path(&[s, s, c])];
+        assert_eq!(ensemble_medoid(&paths), Some(2));
+        assert_eq!(ensemble_medoid(&[]), None);
+
+        let ensemble = simulate_ensemble(&sample_matrix(), StateType::Sunny, 10, 20, 7);
+        assert!(ensemble_medoid(&ensemble).unwrap() < 20);
+    }
+
+    #[test]
+    fn test_steady_state_is_a_clean_distribution() {
+        // Nearly decomposable: two states barely talk to each other
+        let ill_conditioned = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                1.0 - 1e-13, 1e-13, 0.0,
+                1e-13, 1.0 - 2e-13, 1e-13,
+                0.3, 0.3, 0.4,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let check = |distribution: &[f64]| {
+            assert!((distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            assert!(distribution.iter().all(|&p| p >= 0.0));
+        };
+
+        for matrix in [&ill_conditioned, &sample_matrix()] {
+            check(&calculate_steady_state(matrix));
+            check(&steady_state_auto(matrix).distribution);
+            check(&cesaro_limit(matrix, 50));
+            if let Some(exact) = steady_state_exact(matrix) {
+                check(&exact);
+            }
+            if let Ok(eigen) = stationary_eigenvector(matrix) {
+                check(&eigen);
+            }
+        }
+        assert_eq!(clean_distribution(vec![0.5, -1e-17, 0.5]), vec![0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_transition_asymmetry() {
+        let matrix = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.6, 0.2, 0.2,
+                0.1, 0.3, 0.6,
+                0.25, 0.05, 0.7,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        let report = transition_asymmetry(&matrix);
+        assert_eq!(report.len(), 3);
+
+        // Rainy -> Cloudy (0.6) far outweighs Cloudy -> Rainy (0.05)
+        assert_eq!((report[0].0, report[0].1), (StateType::Rainy, StateType::Cloudy));
+        assert!((report[0].2 - 0.55).abs() < 1e-12);
+        assert_eq!((report[1].0, report[1].1), (StateType::Sunny, StateType::Rainy));
+        assert!((report[1].2 - 0.1).abs() < 1e-12);
+        assert!((report[2].2 + 0.05).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_generic_chain_over_words() {
+        let text = ["the", "cat", "sat", "the", "cat", "ran", "the", "dog", "sat"];
+        let matrix = build_chain_matrix(vec!["the", "cat", "dog", "sat", "ran"], &text).unwrap();
+        assert!(matrix.is_stochastic());
+        let the = matrix.state_index("the").unwrap();
+        let cat = matrix.state_index("cat").unwrap();
+        assert!((matrix.matrix[[the, cat]] - 2.0 / 3.0).abs() < 1e-12);
+
+        let words = simulate_chain(&matrix, "the", 6, &mut SeededRandom::new(2)).unwrap();
+        assert_eq!(words.len(), 6);
+        assert_eq!(words[0], "the");
+        assert!(words.iter().all(|w| matrix.state_index(w).is_some()));
+        assert!(matches!(
+            simulate_chain(&matrix, "bird", 6, &mut SeededRandom::new(2)),
+            Err(SimulationError::UnknownState(msg)) if msg.contains("bird")
+        ));
+        assert_eq!(calculate_steady_state(&matrix).len(), 5);
+
+        assert!(build_chain_matrix(vec!["the", "cat"], &text).is_err());
+
+        // Weighted counting sums the weights; unit weights reproduce chain_counts
+        let weighted = chain_counts_weighted(&["the", "cat"], [("the", "cat", 0.5), ("the", "cat", 0.25)]).unwrap();
+        assert_eq!(weighted[[0, 1]], 0.75);
+        assert!(chain_counts_weighted(&["the"], [("the", "dog", 1.0)]).is_err());
+
+        // The weather entry points are thin wrappers over the same machinery
+        let data = parse_weather_data(&sample_weather_json()).unwrap();
+        let sequence: Vec<StateType> = data.iter().map(|w| w.state).collect();
+        let weather = build_chain_matrix(TransitionMatrix::new().states, &sequence).unwrap();
+        assert_eq!(weather, build_transition_matrix(&data));
+    }
+
+    #[test]
+    fn test_coverage() {
+        let data = parse_weather_data(&sample_weather_json()).unwrap();
+        assert_eq!(data.coverage(86400), 1.0);
+
+        // Drop two of the seven days from the middle
+        let mut gappy = data.clone();
+        gappy.states.remove(2);
+        gappy.states.remove(3);
+        assert!((gappy.coverage(86400) - 5.0 / 7.0).abs() < 1e-12);
+
+        let single = HistoricalData { states: data.states[..1].to_vec(), ..data.clone() };
+        assert_eq!(single.coverage(86400), 0.0);
+        assert_eq!(data.coverage(0), 0.0);
+
+        let report = generate_report(&sample_weather_json(), 3, 1, &ParseOptions::default()).unwrap();
+        assert_eq!(report.matrix_data.coverage, Some(1.0));
+    }
+
+    #[test]
+    fn test_append_observation() {
+        let mut history = parse_weather_data(&sample_weather_json()).unwrap();
+        let last = history.states.last().unwrap().timestamp;
+
+        assert!(append_observation(&mut history, WeatherState::new(StateType::Rainy, last)).is_err());
+        append_observation(&mut history, WeatherState::new(StateType::Rainy, last + 86400)).unwrap();
+        assert_eq!(history.len(), 8);
+
+        // The new day's transition is counted on retraining
+        let before = build_transition_matrix(&parse_weather_data(&sample_weather_json()).unwrap());
+        let after = build_transition_matrix(&history);
+        let total = |m: &TransitionMatrix| m.counts.as_ref().unwrap().sum();
+        assert_eq!(total(&after), total(&before) + 1.0);
+    }
+
+    #[test]
+    fn test_imported_matrix_must_list_every_state() {
+        let two_state = r#"{"matrix":{"v":1,"dim":[2,2],"data":[0.9,0.1,0.3,0.7]},"states":["Sunny","Rainy"]}"#;
+        match TransitionMatrix::from_json(two_state) {
+            Err(ParseError::InvalidData(msg)) => assert!(msg.contains("missing state Cloudy")),
+            other => panic!("expected InvalidData, got {:?}", other),
+        }
+
+        // Any ordering of the three states is fine
+        let reordered = r#"{"matrix":{"v":1,"dim":[3,3],"data":[1,0,0,0,1,0,0,0,1]},"states":["Rainy","Cloudy","Sunny"]}"#;
+        assert!(TransitionMatrix::from_json(reordered).is_ok());
+
+        let two_state_bytes = TransitionMatrix::identity(vec![StateType::Sunny, StateType::Rainy]).to_bytes();
+        assert!(TransitionMatrix::from_bytes(&two_state_bytes).is_err());
+    }
+
+    #[test]
+    fn test_statistics_for_hand_entered_matrices() {
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        // Rainy listed first: its steady-state mass must still be reported as rainy
+        let reordered = TransitionMatrix::from_rows(
+            vec![vec![0.8, 0.1, 0.1], vec![0.2, 0.6, 0.2], vec![0.3, 0.3, 0.4]],
+            &names(&["Rainy", "Sunny", "Cloudy"]),
+        ).unwrap();
+        let pi = steady_state_auto(&reordered).distribution;
+        let statistics = compute_statistics(&reordered, None);
+        assert!((statistics.steady_state.rainy - pi[0]).abs() < 1e-6);
+        assert!((statistics.steady_state.sunny - pi[1]).abs() < 1e-6);
+        assert!((statistics.steady_state.cloudy - pi[2]).abs() < 1e-6);
+
+        // A two-state matrix is rejected at import rather than reaching the statistics
+        let two_state = TransitionMatrix::from_rows(
+            vec![vec![0.9, 0.1], vec![0.3, 0.7]],
+            &names(&["Sunny", "Rainy"]),
+        );
+        assert!(matches!(two_state, Err(ParseError::InvalidData(msg)) if msg.contains("missing state Cloudy")));
+    }
+
+    #[test]
+    fn test_states_missing_from_matrix_are_errors() {
+        // Built directly, bypassing import validation, as generic callers can
+        let two_state = TransitionMatrix {
+            matrix: Array2::from_shape_vec((2, 2), vec![0.9, 0.1, 0.3, 0.7]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy],
+            counts: None,
+        };
+        let cloudy = StateType::Cloudy;
+
+        assert!(matches!(two_state.require_state(cloudy), Err(msg) if msg.contains("Cloudy")));
+        assert!(next_state_distribution(&two_state, cloudy).is_err());
+        assert!(forecast_distribution(&two_state, cloudy, 3).is_err());
+        assert!(surprise(&two_state, StateType::Sunny, cloudy).is_err());
+        assert!(hitting_probability(&two_state, StateType::Sunny, cloudy, 3).is_err());
+        assert!(best_initial_state(&two_state, cloudy, 3, Objective::MaximizeExpectedDays).is_err());
+        assert!(SimulationIter::with_rng(&two_state, cloudy, SeededRandom::new(1)).is_err());
+        assert!(simulate_capped(&two_state, cloudy, 5, 2, &mut SeededRandom::new(1)).is_err());
+        assert_eq!(mixing_time(&two_state, cloudy, 1e-3), None);
+
+        let mut data = HistoricalData::new("test".to_string());
+        data.add_state(WeatherState::new(StateType::Sunny, 0));
+        data.add_state(WeatherState::new(cloudy, 86400));
+        assert!(history_probability(&two_state, &data).is_err());
+        assert!(log_likelihood(&two_state, &data).is_err());
+        assert_eq!(least_likely_transition(&two_state, &data), None);
+
+        // Known states still work as before
+        assert_eq!(next_state_distribution(&two_state, StateType::Rainy), Ok(vec![0.3, 0.7]));
+    }
+
+    #[test]
+    fn test_least_likely_transition() {
+        let matrix = sample_matrix();
+        let history = |states: &[StateType]| {
+            let mut data = HistoricalData::new("test".to_string());
+            for (day, &state) in states.iter().enumerate() {
+                data.add_state(WeatherState::new(state, day as i64 * 86400));
+            }
+            data
+        };
+
+        // Fewer than two days means no transitions to rank
+        assert_eq!(least_likely_transition(&matrix, &history(&[])), None);
+        assert_eq!(least_likely_transition(&matrix, &history(&[StateType::Sunny])), None);
+
+        // Sunny -> Rainy (0.1) happens at pairs 0 and 2; the earlier one wins the tie
+        let data = history(&[StateType::Sunny, StateType::Rainy, StateType::Sunny, StateType::Rainy, StateType::Rainy]);
+        assert_eq!(least_likely_transition(&matrix, &data), Some((0, 0.1)));
+
+        let data = history(&[StateType::Cloudy, StateType::Sunny, StateType::Rainy]);
+        assert_eq!(least_likely_transition(&matrix, &data), Some((1, 0.1)));
+    }
+
+    #[test]
+    fn test_classify_many() {
+        let conditions = ["Light rain", "Partly cloudy", "Clear", "Volcanic ash"];
+        let states = classify_many(&conditions);
+        assert_eq!(states, vec![StateType::Rainy, StateType::Cloudy, StateType::Sunny, StateType::Cloudy]);
+
+        // Same answers as classifying one at a time
+        for (condition, state) in conditions.iter().zip(states.iter()) {
+            assert_eq!(classify_weather(condition), *state);
+        }
+        assert!(classify_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_similar_state_pairs() {
+        let matrix = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.6, 0.1, 0.3,
+                0.1, 0.8, 0.1,
+                0.55, 0.15, 0.3,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+
+        // Sunny and Cloudy rows differ by 0.05 in total variation; Rainy is far from both
+        let pairs = similar_state_pairs(&matrix, 0.1);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].0, pairs[0].1), (StateType::Sunny, StateType::Cloudy));
+        assert!((pairs[0].2 - 0.05).abs() < 1e-12);
+
+        // The tolerance is strict, and every pair is listed once in matrix order
+        assert!(similar_state_pairs(&matrix, 0.04).is_empty());
+        let all: Vec<(StateType, StateType)> = similar_state_pairs(&matrix, 1.0).iter().map(|&(a, b, _)| (a, b)).collect();
+        assert_eq!(all, vec![
+            (StateType::Sunny, StateType::Rainy),
+            (StateType::Sunny, StateType::Cloudy),
+            (StateType::Rainy, StateType::Cloudy),
+        ]);
+    }
+
+    #[test]
+    fn test_burn_in_window() {
+        // get_statistics_burn_in(burn_in) summarizes simulation_window(results, burn_in, usize::MAX)
+        let results = simulate_weather_with_rng(&sample_matrix(), StateType::Rainy, 50, &mut SeededRandom::new(8));
+
+        assert_eq!(simulation_window(&results, 0, usize::MAX).len(), results.len());
+        let burned = simulation_window(&results, 20, usize::MAX);
+        assert_eq!(burned.len(), 30);
+        assert_eq!(burned[0].timestamp, results[20].timestamp);
+        assert_eq!(calculate_state_distribution(burned), calculate_state_distribution(&results[20..]));
+
+        // Burning in the whole run (or more) leaves nothing to summarize
+        assert!(simulation_window(&results, 50, usize::MAX).is_empty());
+        assert!(simulation_window(&results, 500, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_persistence_index() {
+        // Weather that never changes always repeats; a strict cycle never does
+        let identity = TransitionMatrix::identity(vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy]);
+        assert!((persistence_index(&identity) - 1.0).abs() < 1e-9);
+        let cycle = TransitionMatrix {
+            matrix: Array2::from_shape_vec((3, 3), vec![
+                0.0, 1.0, 0.0,
+                0.0, 0.0, 1.0,
+                1.0, 0.0, 0.0,
+            ]).unwrap(),
+            states: vec![StateType::Sunny, StateType::Rainy, StateType::Cloudy],
+            counts: None,
+        };
+        assert_eq!(persistence_index(&cycle), 0.0);
+
+        // In general it is the steady-state weighted diagonal
+        let matrix = sample_matrix();
+        let pi = steady_state_auto(&matrix).distribution;
+        let expected: f64 = (0..3).map(|i| pi[i] * matrix.matrix[[i, i]]).sum();
+        assert!((persistence_index(&matrix) - expected).abs() < 1e-12);
+        assert!(persistence_index(&matrix) > 0.0 && persistence_index(&matrix) < 1.0);
+    }
+
+    #[test]
+    fn test_steady_state_subset() {
+        let matrix = sample_matrix();
+        let pi = steady_state_auto(&matrix).distribution;
+
+        assert_eq!(steady_state_subset(&matrix, &[]), 0.0);
+        assert!((steady_state_subset(&matrix, &matrix.states) - 1.0).abs() < 1e-9);
+        assert!((steady_state_subset(&matrix, &[StateType::Sunny]) - pi[0]).abs() < 1e-12);
+
+        // "Not rainy" is everything but the rainy share
+        let not_rainy = steady_state_subset(&matrix, &[StateType::Sunny, StateType::Cloudy]);
+        assert!((not_rainy - (1.0 - pi[1])).abs() < 1e-9);
+
+        // Repeated states are counted once
+        assert_eq!(
+            steady_state_subset(&matrix, &[StateType::Cloudy, StateType::Cloudy, StateType::Cloudy]),
+            steady_state_subset(&matrix, &[StateType::Cloudy]),
+        );
+    }
+
+    #[test]
+    fn test_ensemble_distribution() {
+        let matrix = sample_matrix();
+        let path = |states: &[StateType]| -> Vec<WeatherState> {
+            states.iter().enumerate().map(|(day, &s)| WeatherState::new(s, day as i64 * 86400)).collect()
+        };
+        let paths = vec![
+            path(&[StateType::Sunny, StateType::Rainy]),
+            path(&[StateType::Sunny, StateType::Cloudy]),
+        ];
+        assert_eq!(ensemble_distribution(&matrix, &paths).unwrap(), vec![vec![1.0, 0.0, 0.0], vec![0.0, 0.5, 0.5]]);
+        assert!(ensemble_distribution(&matrix, &[]).unwrap().is_empty());
+
+        // A later path longer than the first is rejected rather than indexed past the end
+        let ragged = vec![path(&[StateType::Sunny]), path(&[StateType::Sunny, StateType::Rainy])];
+        assert!(ensemble_distribution(&matrix, &ragged).is_err());
+    }
+
+    #[test]
+    fn test_simulate_capped_reports_random_failure() {
+        let matrix = sample_matrix();
+        let err = simulate_capped(&matrix, StateType::Sunny, 5, 2, &mut NoEntropy).unwrap_err();
+        assert!(err.starts_with("Random number generation unavailable"));
     }
 }